@@ -0,0 +1,689 @@
+use super::*;
+use libflock::evaluator::{
+    Command, Evaluator, EvaluatorError, Event, Graph, Junction, SignalId,
+    MAX_SIGNALS
+};
+use libflock::input::AudioInput;
+use libflock::queue::Queue;
+use libflock::signals::{
+    AudioIn, AudioOut, Biquad, BrownNoise, Connection, Envelope, Fan,
+    FilterResponse, PinkNoise, Pulse, Saw, Signal, Sine, Square, Triangle,
+    Value, WhiteNoise
+};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+
+// The number of slots in the queues between
+// a Nexus and the Composition it controls.
+pub const QUEUE_SIZE: usize = 256;
+pub type CommandQueue = Queue<Command<'static>, QUEUE_SIZE>;
+pub type EventQueue = Queue<Event<'static>, QUEUE_SIZE>;
+
+#[derive(Debug)]
+pub enum BuildError {
+    Invalid(SpecError),
+    // The evaluator rejected a signal or one of its connections.
+    Graph { signal: String, error: EvaluatorError }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::Invalid(e) => write!(f, "{}", e),
+            BuildError::Graph { signal, error } =>
+                write!(f, "Signal {} couldn't be added to the graph: {}",
+                    signal, error)
+        }
+    }
+}
+
+impl Error for BuildError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BuildError::Invalid(e) => Some(e),
+            BuildError::Graph { .. } => None
+        }
+    }
+}
+
+impl From<SpecError> for BuildError {
+    fn from(e: SpecError) -> Self {
+        BuildError::Invalid(e)
+    }
+}
+
+// A live graph of libflock signals built from a CompositionSpec.
+// The Evaluator can only borrow signals and Junctions, so the Composition
+// leaks them for the lifetime of the Evaluator
+// and reclaims them when it is dropped.
+pub struct Composition {
+    evaluator: Evaluator<'static>,
+    ids: HashMap<String, SignalId>,
+    // The frames of the environment's input device, if it has one.
+    input: Arc<AudioInput>,
+    // Present when the Composition is controlled by a Nexus.
+    commands: Option<Arc<CommandQueue>>,
+    events: Option<Arc<EventQueue>>
+}
+
+impl Composition {
+    pub fn new(settings: AudioSettings) -> Composition {
+        // Enough Junctions for every signal to run at a smaller block size.
+        let junctions: Vec<Junction> = (0..MAX_SIGNALS)
+            .map(|_| Junction::new())
+            .collect();
+
+        Composition {
+            evaluator: Evaluator::with_block_size(settings.block_size,
+                Box::leak(junctions.into_boxed_slice())),
+            ids: HashMap::new(),
+            input: Arc::new(AudioInput::new(settings)),
+            commands: None,
+            events: None
+        }
+    }
+
+    // The libflock IDs of the signals the Composition was built with.
+    pub fn ids(&self) -> &HashMap<String, SignalId> {
+        &self.ids
+    }
+
+    pub fn signal_id(&self, id: &str) -> Option<SignalId> {
+        self.ids.get(id).copied()
+    }
+
+    // The AudioInput that the Composition's AudioIn signals read from.
+    pub fn input(&self) -> &Arc<AudioInput> {
+        &self.input
+    }
+
+    // Applies commands from the queue before each block is evaluated,
+    // and reports their outcome to the event queue.
+    pub(crate) fn attach(
+        &mut self,
+        commands: Arc<CommandQueue>,
+        events: Arc<EventQueue>
+    ) {
+        self.commands = Some(commands);
+        self.events = Some(events);
+    }
+
+    pub fn signal(&self, id: &str) -> Option<&dyn Signal> {
+        self.signal_id(id).and_then(|signal_id|
+            self.evaluator.signal(signal_id))
+    }
+
+    fn add(&mut self, id: &str, signal: Box<dyn Signal>, rate: Option<Rate>) ->
+        Result<SignalId, BuildError> {
+        let graph_error = |error| BuildError::Graph {
+            signal: id.to_string(),
+            error
+        };
+
+        // The signal is freed when it's removed from the Evaluator.
+        let signal_id = self.evaluator.add(Box::leak(signal))
+            .map_err(graph_error)?;
+        self.evaluator.set_control_rate(signal_id, rate == Some(Rate::Control))
+            .map_err(graph_error)?;
+        self.ids.insert(id.to_string(), signal_id);
+
+        Ok(signal_id)
+    }
+}
+
+impl Drop for Composition {
+    fn drop(&mut self) {
+        for id in 0..MAX_SIGNALS {
+            if let Ok(signal) = self.evaluator.remove(id) {
+                // Every signal in the Evaluator was leaked from a Box.
+                unsafe {
+                    drop(Box::from_raw(signal as *mut dyn Signal));
+                }
+            }
+        }
+
+        // The Junctions were leaked from a boxed slice.
+        let junctions = self.evaluator.release_junctions();
+        unsafe {
+            drop(Box::from_raw(junctions as *mut [Junction]));
+        }
+    }
+}
+
+impl Graph for Composition {
+    fn evaluate(&mut self) {
+        if let (Some(commands), Some(events)) = (&self.commands, &self.events) {
+            // The Composition is the queues' only consumer of commands
+            // and only producer of events.
+            let (mut commands, mut events) = unsafe {
+                (commands.consumer(), events.producer())
+            };
+
+            // If the Nexus isn't keeping up with events, removed signals
+            // are leaked rather than freed on the realtime thread.
+            self.evaluator.drain(&mut commands, |event| {
+                let _ = events.push(event);
+            });
+        }
+
+        // The Composition is the input's only reader,
+        // and its signals only generate during evaluate().
+        unsafe {
+            self.input.read();
+        }
+
+        self.evaluator.evaluate()
+    }
+
+    fn output(&self, channel: usize) -> Option<&[f32; MAX_BLOCK_SIZE]> {
+        Graph::output(&self.evaluator, channel)
+    }
+}
+
+// Control-rate signals generate a single sample per block,
+// so their sample rate is the environment's block rate.
+// Other signals may override the environment's block size
+// with one that divides it, to be generated several times per block.
+pub fn signal_settings(spec: &SignalSpec, settings: AudioSettings) ->
+    AudioSettings {
+    match (spec.rate, spec.block_size) {
+        (Some(Rate::Control), _) => AudioSettings {
+            sample_rate: settings.sample_rate / settings.block_size as f32,
+            block_size: 1,
+            ..settings
+        },
+        (_, Some(block_size)) => AudioSettings {
+            block_size: block_size as usize,
+            ..settings
+        },
+        _ => settings
+    }
+}
+
+// The spec's seed, or one from the OS's randomness, by way of
+// the randomly keyed hasher that HashMaps use.
+fn seed(spec: &SignalSpec) -> u64 {
+    spec.seed.unwrap_or_else(|| RandomState::new().build_hasher().finish())
+}
+
+// Creates a signal of the specified type,
+// with its parameters and constant inputs set from the spec.
+// AudioIn signals read from the input, which must outlive them.
+pub fn create_signal(
+    spec: &SignalSpec,
+    settings: AudioSettings,
+    input: &AudioInput
+) -> Option<Box<dyn Signal>> {
+    let mut signal: Box<dyn Signal> = match spec.signal_type.as_str() {
+        "value" => Box::new(Value::new(settings)),
+        "sine" => Box::new(Sine::new(settings)),
+        "saw" => Box::new(Saw::new(settings)),
+        "square" => Box::new(Square::new(settings)),
+        "triangle" => Box::new(Triangle::new(settings)),
+        "pulse" => Box::new(Pulse::new(settings)),
+        "white_noise" => Box::new(WhiteNoise::new(settings, seed(spec))),
+        "pink_noise" => Box::new(PinkNoise::new(settings, seed(spec))),
+        "brown_noise" => Box::new(BrownNoise::new(settings, seed(spec))),
+        "adsr" => Box::new(Envelope::adsr(settings, 0.01, 0.1, 0.5, 0.3)),
+        "low_pass" => Box::new(Biquad::new(settings, FilterResponse::LowPass)),
+        "high_pass" =>
+            Box::new(Biquad::new(settings, FilterResponse::HighPass)),
+        "band_pass" =>
+            Box::new(Biquad::new(settings, FilterResponse::BandPass)),
+        "notch" => Box::new(Biquad::new(settings, FilterResponse::Notch)),
+        "peaking" => Box::new(Biquad::new(settings, FilterResponse::Peaking)),
+        "low_shelf" =>
+            Box::new(Biquad::new(settings, FilterResponse::LowShelf)),
+        "high_shelf" =>
+            Box::new(Biquad::new(settings, FilterResponse::HighShelf)),
+        "fan" => Box::new(Fan::new(settings)),
+        "audio_out" => Box::new(AudioOut::new(settings)),
+        "audio_in" => Box::new(unsafe {
+            AudioIn::new(settings, input.block())
+        }),
+        _ => return None
+    };
+
+    let signal_type = signal_types::find(&spec.signal_type)?;
+    for (name, value) in spec.parameters.iter().flatten() {
+        let parameter = signal_type.parameter_index(name)
+            .and_then(|index| signal.parameter_mut(index))?;
+        *parameter = *value;
+    }
+
+    for (name, value) in spec.inputs.iter().flatten() {
+        let input = signal_type.input_index(name)
+            .and_then(|index| signal.input_mut(index))?;
+        *input = Connection::new_constant(*value);
+    }
+
+    Some(signal)
+}
+
+// The index of the target signal's input that the connection refers to.
+pub fn input_index(target: &SignalSpec, connection: &ConnectionSpec) ->
+    Result<usize, SpecError> {
+    signal_types::find(&target.signal_type)
+        .and_then(|signal_type| signal_type.input_index(&connection.input))
+        .ok_or_else(|| SpecError::UnknownInput {
+            signal: connection.target.clone(),
+            input: connection.input.clone()
+        })
+}
+
+// Control-rate sources only generate their first sample,
+// so by default they're read at control rate.
+pub fn step_size(connection: &ConnectionSpec, source: &SignalSpec) -> usize {
+    match (connection.step_size, source.rate) {
+        (Some(step_size), _) => step_size as usize,
+        (None, Some(Rate::Control)) => 0,
+        (None, _) => 1
+    }
+}
+
+// Instantiates the composition's signals and connections.
+// Signals and connections are added in order of their IDs,
+// so that the same spec always produces the same graph.
+pub fn build(composition: &CompositionSpec, settings: AudioSettings) ->
+    Result<Composition, BuildError> {
+    composition.validate()?;
+
+    let empty_signals = HashMap::new();
+    let signals = composition.signals.as_ref().unwrap_or(&empty_signals);
+    let mut built = Composition::new(settings);
+
+    let mut signal_ids: Vec<&String> = signals.keys().collect();
+    signal_ids.sort();
+    for id in signal_ids {
+        let spec = &signals[id];
+        let signal = create_signal(spec, signal_settings(spec, settings),
+            &built.input)
+            .ok_or_else(|| SpecError::UnknownSignalType {
+                signal: id.clone(),
+                signal_type: spec.signal_type.clone()
+            })?;
+        built.add(id, signal, spec.rate)?;
+    }
+
+    let mut connections: Vec<(&String, &ConnectionSpec)> =
+        composition.connections.iter().flatten().collect();
+    connections.sort_by_key(|(id, _)| *id);
+    for (_, connection) in connections {
+        let input = input_index(&signals[&connection.target], connection)?;
+
+        built.evaluator.connect(
+            built.ids[&connection.source],
+            connection.channel.unwrap_or(0) as usize,
+            built.ids[&connection.target],
+            input,
+            step_size(connection, &signals[&connection.source])
+        ).map_err(|error| BuildError::Graph {
+            signal: connection.target.clone(),
+            error
+        })?;
+    }
+
+    if let Some(output) = &composition.output {
+        built.evaluator.set_output(built.ids[output])
+            .map_err(|error| BuildError::Graph {
+                signal: output.clone(),
+                error
+            })?;
+    }
+
+    Ok(built)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn composition(
+        signals: Vec<(&str, SignalSpec)>,
+        connections: Vec<(&str, ConnectionSpec)>,
+        output: Option<&str>
+    ) -> CompositionSpec {
+        CompositionSpec {
+            environment: EnvironmentSettings {
+                sample_rate: Some(48000),
+                block_size: Some(32),
+                num_output_channels: Some(2),
+                ..Default::default()
+            },
+            signals: Some(signals.into_iter()
+                .map(|(id, spec)| (id.to_string(), spec)).collect()),
+            connections: Some(connections.into_iter()
+                .map(|(id, spec)| (id.to_string(), spec)).collect()),
+            output: output.map(|id| id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn builds_a_playable_graph() {
+        let mut sine = SignalSpec::new("sine");
+        sine.inputs = Some([("mul".to_string(), 0.5)].into());
+        let mut freq = SignalSpec::new("value");
+        freq.parameters = Some([("value".to_string(), 500.0)].into());
+
+        let spec = composition(vec![
+            ("freq", freq),
+            ("sine", sine),
+            ("out", SignalSpec::new("fan"))
+        ], vec![
+            ("pitch", ConnectionSpec::new("freq", "sine", "freq")),
+            ("speakers", ConnectionSpec::new("sine", "out", "source"))
+        ], Some("out"));
+
+        let settings = spec.environment.audio_settings();
+        let mut graph = build(&spec, settings).unwrap();
+        graph.evaluate();
+
+        let mut expected = Sine::new(settings);
+        expected.inputs.freq = Connection::new_constant(500.0);
+        expected.inputs.mul = Connection::new_constant(0.5);
+        expected.generate();
+
+        for channel in 0..2 {
+            assert_eq!(expected.output.samples[0..32],
+                graph.output(channel).unwrap()[0..32],
+                "Channel {} plays the sine", channel);
+        }
+        assert!(graph.output(2).is_none());
+    }
+
+    #[test]
+    fn every_signal_type_can_be_created() {
+        let settings = EnvironmentSettings::default().audio_settings();
+        let input = AudioInput::new(settings);
+
+        for signal_type in signal_types::SIGNAL_TYPES {
+            let mut signal = create_signal(&SignalSpec::new(signal_type.name),
+                settings, &input).unwrap();
+
+            let defaults: Vec<f32> = (0..)
+                .map_while(|index| signal.input(index))
+                .map(|input| input.value)
+                .collect();
+            assert_eq!(signal_type.input_defaults, &defaults[..],
+                "The {} signal's inputs match its type", signal_type.name);
+            assert_eq!(signal_type.inputs.len(), defaults.len());
+
+            let num_parameters = signal_type.parameters.len();
+            assert!(signal.parameter_mut(num_parameters).is_none());
+            assert!(num_parameters == 0 ||
+                signal.parameter_mut(num_parameters - 1).is_some());
+        }
+    }
+
+    #[test]
+    fn seeded_noise_is_reproducible() {
+        let mut seeded = SignalSpec::new("pink_noise");
+        seeded.seed = Some(1234);
+        let spec = composition(vec![("noise", seeded)], vec![], Some("noise"));
+        let settings = spec.environment.audio_settings();
+
+        let mut first = build(&spec, settings).unwrap();
+        let mut second = build(&spec, settings).unwrap();
+        first.evaluate();
+        second.evaluate();
+        assert_eq!(first.output(0).unwrap(), second.output(0).unwrap());
+
+        let unseeded = composition(vec![
+            ("noise", SignalSpec::new("white_noise"))
+        ], vec![], Some("noise"));
+        let mut first = build(&unseeded, settings).unwrap();
+        let mut second = build(&unseeded, settings).unwrap();
+        first.evaluate();
+        second.evaluate();
+        assert_ne!(first.output(0).unwrap(), second.output(0).unwrap(),
+            "Unseeded noise is seeded from the OS");
+    }
+
+    #[test]
+    fn adsr_parameters_shape_its_segments() {
+        let mut gate = SignalSpec::new("value");
+        gate.parameters = Some([("value".to_string(), 1.0)].into());
+        let mut adsr = SignalSpec::new("adsr");
+        adsr.parameters = Some([
+            ("attack".to_string(), 0.0),
+            ("decay".to_string(), 0.0),
+            ("sustain".to_string(), 0.25)
+        ].into());
+
+        let spec = composition(vec![("gate", gate), ("adsr", adsr)],
+            vec![("opening", ConnectionSpec::new("gate", "adsr", "gate"))],
+            Some("adsr"));
+        let mut graph = build(&spec, spec.environment.audio_settings())
+            .unwrap();
+        graph.evaluate();
+
+        assert!(graph.output(0).unwrap()[0..32].iter()
+            .all(|sample| *sample == 0.25));
+    }
+
+    #[test]
+    fn filters_shape_their_sources() {
+        let mut offset = SignalSpec::new("value");
+        offset.parameters = Some([("value".to_string(), 1.0)].into());
+        let spec = composition(vec![
+            ("offset", offset),
+            ("low", SignalSpec::new("low_pass")),
+            ("high", SignalSpec::new("high_pass"))
+        ], vec![
+            ("lows", ConnectionSpec::new("offset", "low", "source")),
+            ("highs", ConnectionSpec::new("offset", "high", "source"))
+        ], None);
+        let mut graph = build(&spec, spec.environment.audio_settings())
+            .unwrap();
+        for _ in 0..100 {
+            graph.evaluate();
+        }
+
+        let low = graph.signal("low").unwrap().output(0).unwrap();
+        let high = graph.signal("high").unwrap().output(0).unwrap();
+        assert!((low[31] - 1.0).abs() < 0.001, "Low pass: {}", low[31]);
+        assert!(high[31].abs() < 0.001, "High pass: {}", high[31]);
+    }
+
+    #[test]
+    fn control_rate_signals_generate_one_sample() {
+        let mut lfo = SignalSpec::new("sine");
+        lfo.rate = Some(Rate::Control);
+        lfo.inputs = Some([
+            ("phase_offset".to_string(), core::f32::consts::PI / 2.0)
+        ].into());
+        let mut carrier = SignalSpec::new("sine");
+        carrier.block_size = Some(16);
+
+        let spec = composition(vec![("lfo", lfo), ("carrier", carrier)],
+            vec![], None);
+        let mut graph = build(&spec, spec.environment.audio_settings())
+            .unwrap();
+        graph.evaluate();
+
+        let lfo = graph.signal("lfo").unwrap().output(0).unwrap();
+        assert_eq!(1.0, lfo[0]);
+        assert_eq!(0.0, lfo[1], "Only the first sample was generated");
+
+        let carrier = graph.signal("carrier").unwrap().output(0).unwrap();
+        assert_ne!(0.0, carrier[15]);
+        assert_eq!(0.0, carrier[16], "Only 16 samples were generated at once");
+
+        assert!(graph.output(0).is_none(),
+            "A composition without an output is silent");
+    }
+
+    #[test]
+    fn control_rate_signals_keep_their_frequency() {
+        let mut lfo = SignalSpec::new("sine");
+        lfo.rate = Some(Rate::Control);
+        lfo.inputs = Some([
+            ("freq".to_string(), 375.0),
+            ("phase_offset".to_string(), core::f32::consts::PI / 2.0)
+        ].into());
+
+        // 48 kHz in blocks of 32 is a control rate of 1500 Hz,
+        // so a 375 Hz LFO takes four blocks per cycle.
+        let spec = composition(vec![("lfo", lfo)], vec![], Some("lfo"));
+        let mut graph = build(&spec, spec.environment.audio_settings())
+            .unwrap();
+        let expected = [1.0, 0.0, -1.0, 0.0, 1.0];
+        for expected in expected {
+            graph.evaluate();
+            let actual = graph.output(0).unwrap()[0];
+            assert!((expected - actual).abs() < 0.0001,
+                "Expected {}, got {}", expected, actual);
+        }
+    }
+
+    #[test]
+    fn smaller_blocks_fill_the_environments_block() {
+        let mut sine = SignalSpec::new("sine");
+        sine.block_size = Some(1);
+        let spec = composition(vec![
+            ("sine", sine),
+            ("out", SignalSpec::new("audio_out"))
+        ], vec![
+            ("left", ConnectionSpec::new("sine", "out", "source_0"))
+        ], Some("out"));
+        let settings = spec.environment.audio_settings();
+        let mut graph = build(&spec, settings).unwrap();
+        graph.evaluate();
+
+        let mut expected = Sine::new(settings);
+        expected.generate();
+        for (expected, actual) in expected.output.samples.iter()
+            .zip(graph.output(0).unwrap()).take(32) {
+            assert!((expected - actual).abs() < 0.0001);
+        }
+
+        let mut uneven = SignalSpec::new("sine");
+        uneven.block_size = Some(24);
+        let spec = composition(vec![("sine", uneven)], vec![], None);
+        assert!(matches!(build(&spec, settings),
+            Err(BuildError::Invalid(SpecError::InvalidBlockSize { .. }))),
+            "Block sizes must divide the environment's");
+    }
+
+    #[test]
+    fn feedback_connections_are_delayed_by_a_block() {
+        let mut sine = SignalSpec::new("sine");
+        sine.inputs = Some([("freq".to_string(), 0.0)].into());
+        let mut level = SignalSpec::new("value");
+        level.parameters = Some([("value".to_string(), 0.5)].into());
+        let mut out = SignalSpec::new("audio_out");
+        out.parameters = Some([("channel_1".to_string(), 0.0)].into());
+
+        // The output is fed back through the silent Sine's add input,
+        // so each block is the previous one plus the level.
+        let spec = composition(vec![
+            ("level", level),
+            ("sine", sine),
+            ("out", out)
+        ], vec![
+            ("a", ConnectionSpec::new("level", "out", "source_0")),
+            ("b", ConnectionSpec::new("out", "sine", "add")),
+            ("c", ConnectionSpec::new("sine", "out", "source_1"))
+        ], Some("out"));
+        let mut graph = build(&spec, spec.environment.audio_settings())
+            .unwrap();
+
+        for block in 1..4 {
+            graph.evaluate();
+            assert_eq!([0.5 * block as f32; 32], graph.output(0).unwrap()[0..32]);
+        }
+    }
+
+    #[test]
+    fn audio_out_maps_sources_to_channels() {
+        let mut quiet = SignalSpec::new("value");
+        quiet.parameters = Some([("value".to_string(), 0.25)].into());
+        let mut loud = SignalSpec::new("value");
+        loud.parameters = Some([("value".to_string(), 0.5)].into());
+        let mut out = SignalSpec::new("audio_out");
+        out.parameters = Some([
+            ("channel_0".to_string(), 1.0),
+            ("channel_1".to_string(), 1.0)
+        ].into());
+
+        let spec = composition(vec![
+            ("quiet", quiet),
+            ("loud", loud),
+            ("out", out)
+        ], vec![
+            ("a", ConnectionSpec::new("quiet", "out", "source_0")),
+            ("b", ConnectionSpec::new("loud", "out", "source_1"))
+        ], Some("out"));
+        let mut graph = build(&spec, spec.environment.audio_settings())
+            .unwrap();
+        graph.evaluate();
+
+        assert_eq!([0.0; 32], graph.output(0).unwrap()[0..32]);
+        assert_eq!([0.75; 32], graph.output(1).unwrap()[0..32],
+            "Sources mapped to the same channel are summed");
+    }
+
+    #[test]
+    fn audio_in_plays_the_input_device() {
+        let spec = composition(vec![
+            ("mic", SignalSpec::new("audio_in")),
+            ("out", SignalSpec::new("fan"))
+        ], vec![
+            ("right", ConnectionSpec {
+                channel: Some(1),
+                ..ConnectionSpec::new("mic", "out", "source")
+            })
+        ], Some("out"));
+        let mut graph = build(&spec, spec.environment.audio_settings())
+            .unwrap();
+
+        let input = graph.input().clone();
+        for i in 0..input.latency() {
+            unsafe {
+                input.write(&[0.0, i as f32]);
+            }
+        }
+        graph.evaluate();
+
+        let out = graph.output(0).unwrap();
+        assert_eq!(1.0, out[1]);
+        assert_eq!(31.0, out[31]);
+
+        let mut split = SignalSpec::new("audio_in");
+        split.block_size = Some(16);
+        let spec = composition(vec![("mic", split)], vec![], None);
+        assert!(matches!(build(&spec, spec.environment.audio_settings()),
+            Err(BuildError::Invalid(SpecError::InvalidBlockSize { .. }))),
+            "AudioIn signals read whole blocks");
+    }
+
+    #[test]
+    fn errors_name_the_offending_signal() {
+        let signals = (0..=MAX_SIGNALS)
+            .map(|n| (format!("sine_{:03}", n), SignalSpec::new("sine")))
+            .collect::<Vec<_>>();
+        let spec = composition(signals.iter()
+            .map(|(id, spec)| (id.as_str(), spec.clone())).collect(),
+            vec![], None);
+
+        match build(&spec, spec.environment.audio_settings()) {
+            Err(BuildError::Graph { signal, error }) => {
+                assert_eq!(format!("sine_{:03}", MAX_SIGNALS), signal);
+                assert_eq!(EvaluatorError::TooManySignals, error);
+            },
+            _ => panic!("Expected the last signal to be rejected")
+        }
+
+        let mut mistyped = SignalSpec::new("sine");
+        mistyped.signal_type = "sin".to_string();
+        let spec = composition(vec![("osc", mistyped)], vec![], None);
+        match build(&spec, spec.environment.audio_settings()) {
+            Err(BuildError::Invalid(SpecError::UnknownSignalType {
+                signal, ..
+            })) => assert_eq!("osc", signal),
+            _ => panic!("Expected the unknown type to be rejected")
+        }
+    }
+}
@@ -1,7 +1,51 @@
 use super::*;
 
-pub fn parse_composition(composition_spec_json: &str) -> Result<CompositionSpec, serde_json::Error> {
-    serde_json::from_str::<CompositionSpec>(composition_spec_json)
+#[derive(Debug)]
+pub enum ParseError {
+    Json(serde_json::Error),
+    Invalid(SpecError)
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Json(e) => write!(f, "Invalid JSON: {}", e),
+            ParseError::Invalid(e) => write!(f, "Invalid composition: {}", e)
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Json(e) => Some(e),
+            ParseError::Invalid(e) => Some(e)
+        }
+    }
+}
+
+impl From<serde_json::Error> for ParseError {
+    fn from(e: serde_json::Error) -> Self {
+        ParseError::Json(e)
+    }
+}
+
+impl From<SpecError> for ParseError {
+    fn from(e: SpecError) -> Self {
+        ParseError::Invalid(e)
+    }
+}
+
+pub fn parse_composition(composition_spec_json: &str) -> Result<CompositionSpec, ParseError> {
+    let composition = serde_json::from_str::<CompositionSpec>(
+        composition_spec_json)?;
+    composition.validate()?;
+
+    Ok(composition)
+}
+
+pub fn to_json(composition: &CompositionSpec) -> Result<String, serde_json::Error> {
+    serde_json::to_string(composition)
 }
 
 #[cfg(test)]
@@ -34,17 +78,292 @@ mod tests {
                 num_input_channels: Some(2),
                 num_output_channels: Some(2),
                 sample_rate: Some(44100),
+                input_sample_rate: None,
                 buffer_size: Some(128),
-                block_size: Some(4)
+                block_size: Some(4),
+                strict: None
             },
             signals: Some(
                 HashMap::<String, SignalSpec>::new()
             ),
-            connections: None
+            connections: None,
+            output: None,
+            osc: None,
+            midi: None
         };
 
         let actual = json::parse_composition(composition_spec_json).unwrap();
         assert_eq!(expected, actual,
             "EnvironmentSettings were correctly parsed from JSON");
     }
+
+    #[test]
+    fn signals_round_trip() {
+        let composition_spec_json = r#"{
+            "environment": {},
+            "signals": {
+                "lfo": {
+                    "type": "sine",
+                    "inputs": {
+                        "freq": 2,
+                        "mul": 100,
+                        "add": 300
+                    },
+                    "rate": "control"
+                },
+                "carrier": {
+                    "type": "sine",
+                    "block_size": 32
+                },
+                "level": {
+                    "type": "value",
+                    "parameters": {
+                        "value": 0.5
+                    }
+                },
+                "hiss": {
+                    "type": "white_noise",
+                    "seed": 18446744073709551615
+                }
+            }
+        }"#;
+
+        let parsed = json::parse_composition(composition_spec_json).unwrap();
+        let signals = parsed.signals.as_ref().unwrap();
+
+        let lfo = &signals["lfo"];
+        assert_eq!("sine", lfo.signal_type);
+        assert_eq!(Some(Rate::Control), lfo.rate);
+        assert_eq!(Some(&300.0), lfo.inputs.as_ref().unwrap().get("add"));
+        assert_eq!(Some(32), signals["carrier"].block_size);
+        assert_eq!(None, signals["carrier"].inputs);
+        assert_eq!(Some(u64::MAX), signals["hiss"].seed);
+        assert_eq!(Some(&0.5),
+            signals["level"].parameters.as_ref().unwrap().get("value"));
+
+        let serialized = json::to_json(&parsed).unwrap();
+        assert_eq!(parsed, json::parse_composition(&serialized).unwrap(),
+            "The composition survives a round trip through JSON");
+    }
+
+    #[test]
+    fn unknown_signal_types_are_rejected() {
+        let composition_spec_json = r#"{
+            "environment": {},
+            "signals": {
+                "osc": {
+                    "type": "sawtooth"
+                }
+            }
+        }"#;
+
+        match json::parse_composition(composition_spec_json) {
+            Err(json::ParseError::Invalid(e)) => assert_eq!(
+                SpecError::UnknownSignalType {
+                    signal: "osc".to_string(),
+                    signal_type: "sawtooth".to_string()
+                }, e),
+            other => panic!("Expected an unknown type error: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn unknown_inputs_and_parameters_are_rejected() {
+        let mut sine = SignalSpec::new("sine");
+        sine.inputs = Some([("frequency".to_string(), 440.0)].into());
+        assert_eq!(Err(SpecError::UnknownInput {
+            signal: "osc".to_string(),
+            input: "frequency".to_string()
+        }), sine.validate("osc"));
+
+        let mut value = SignalSpec::new("value");
+        value.parameters = Some([("val".to_string(), 1.0)].into());
+        assert_eq!(Err(SpecError::UnknownParameter {
+            signal: "level".to_string(),
+            parameter: "val".to_string()
+        }), value.validate("level"));
+
+        let mut fan = SignalSpec::new("fan");
+        fan.block_size = Some(0);
+        assert!(fan.validate("out").is_err());
+        fan.block_size = Some(MAX_BLOCK_SIZE as u32 + 1);
+        assert_eq!(Err(SpecError::InvalidBlockSize {
+            signal: "out".to_string(),
+            block_size: MAX_BLOCK_SIZE as u32 + 1
+        }), fan.validate("out"));
+    }
+
+    #[test]
+    fn connections_are_parsed() {
+        let composition_spec_json = r#"{
+            "environment": {},
+            "signals": {
+                "lfo": { "type": "sine", "rate": "control" },
+                "carrier": { "type": "sine" },
+                "out": { "type": "fan" }
+            },
+            "connections": {
+                "vibrato": {
+                    "source": "lfo",
+                    "target": "carrier",
+                    "input": "freq",
+                    "step_size": 0
+                },
+                "speakers": {
+                    "source": "carrier",
+                    "channel": 0,
+                    "target": "out",
+                    "input": "source"
+                }
+            }
+        }"#;
+
+        let parsed = json::parse_composition(composition_spec_json).unwrap();
+        let connections = parsed.connections.as_ref().unwrap();
+
+        let mut expected = ConnectionSpec::new("lfo", "carrier", "freq");
+        expected.step_size = Some(0);
+        assert_eq!(expected, connections["vibrato"]);
+        assert_eq!(Some(0), connections["speakers"].channel);
+        assert_eq!(None, connections["speakers"].step_size);
+
+        let serialized = json::to_json(&parsed).unwrap();
+        assert_eq!(parsed, json::parse_composition(&serialized).unwrap());
+    }
+
+    #[test]
+    fn invalid_connections_are_rejected() {
+        let mut composition = CompositionSpec {
+            environment: EnvironmentSettings::default(),
+            signals: Some([
+                ("osc".to_string(), SignalSpec::new("sine")),
+                ("out".to_string(), SignalSpec::new("fan"))
+            ].into()),
+            connections: Some([(
+                "speakers".to_string(),
+                ConnectionSpec::new("oscillator", "out", "source")
+            )].into()),
+            output: Some("out".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(Err(SpecError::UnknownSignal {
+            connection: "speakers".to_string(),
+            signal: "oscillator".to_string()
+        }), composition.validate());
+
+        composition.connections = Some([(
+            "speakers".to_string(),
+            ConnectionSpec::new("osc", "out", "input")
+        )].into());
+        assert_eq!(Err(SpecError::UnknownInput {
+            signal: "out".to_string(),
+            input: "input".to_string()
+        }), composition.validate());
+
+        composition.connections = Some([
+            ("left".to_string(), ConnectionSpec::new("osc", "out", "source")),
+            ("right".to_string(), ConnectionSpec::new("osc", "out", "source"))
+        ].into());
+        assert_eq!(Err(SpecError::DuplicateConnection {
+            signal: "out".to_string(),
+            input: "source".to_string()
+        }), composition.validate());
+    }
+
+    #[test]
+    fn out_of_range_environment_block_sizes_are_rejected() {
+        let parsed = json::parse_composition(r#"{
+            "environment": { "block_size": 4096 }
+        }"#);
+        assert!(matches!(parsed, Err(ParseError::Invalid(
+            SpecError::InvalidEnvironmentBlockSize { block_size: 4096 }))));
+
+        let mut composition = CompositionSpec::default();
+
+        composition.environment.block_size = Some(0);
+        assert_eq!(Err(SpecError::InvalidEnvironmentBlockSize {
+            block_size: 0
+        }), composition.validate());
+
+        composition.environment.block_size = Some(MAX_BLOCK_SIZE as u32);
+        assert_eq!(Ok(()), composition.validate());
+    }
+
+    #[test]
+    fn osc_addresses_are_parsed_and_validated() {
+        let composition_spec_json = r#"{
+            "environment": {},
+            "signals": {
+                "carrier": { "type": "sine" }
+            },
+            "osc": {
+                "port": 9000,
+                "addresses": {
+                    "/fader/1": {
+                        "signal": "carrier",
+                        "input": "freq",
+                        "mul": 1000,
+                        "add": 100
+                    }
+                }
+            }
+        }"#;
+
+        let mut parsed = json::parse_composition(composition_spec_json)
+            .unwrap();
+        assert_eq!(Ok(()), parsed.validate());
+
+        let osc = parsed.osc.as_mut().unwrap();
+        assert_eq!(Some(9000), osc.port);
+        let fader = osc.addresses.as_mut().unwrap()
+            .get_mut("/fader/1").unwrap();
+        assert_eq!(600.0, fader.scale(0.5));
+
+        fader.parameter = Some("value".to_string());
+        assert_eq!(Err(SpecError::InvalidControl {
+            control: "/fader/1".to_string()
+        }), parsed.validate());
+    }
+
+    #[test]
+    fn midi_controls_are_parsed_and_validated() {
+        let composition_spec_json = r#"{
+            "environment": {},
+            "signals": {
+                "carrier": { "type": "sine" }
+            },
+            "midi": {
+                "device": "/dev/snd/midiC1D0",
+                "controls": {
+                    "pitch": {
+                        "message": "frequency",
+                        "signal": "carrier",
+                        "input": "freq"
+                    },
+                    "volume": {
+                        "message": "control",
+                        "number": 7,
+                        "channel": 0,
+                        "signal": "carrier",
+                        "input": "mul"
+                    }
+                }
+            }
+        }"#;
+
+        let mut parsed = json::parse_composition(composition_spec_json)
+            .unwrap();
+        assert_eq!(Ok(()), parsed.validate());
+
+        let serialized = json::to_json(&parsed).unwrap();
+        assert_eq!(parsed, json::parse_composition(&serialized).unwrap());
+
+        let controls = parsed.midi.as_mut().unwrap()
+            .controls.as_mut().unwrap();
+        assert_eq!(MidiMessageType::Frequency, controls["pitch"].message);
+        controls.get_mut("volume").unwrap().number = None;
+        assert_eq!(Err(SpecError::InvalidMidiControl {
+            control: "volume".to_string()
+        }), parsed.validate());
+    }
 }
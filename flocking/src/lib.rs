@@ -1,13 +1,18 @@
+pub mod builder;
 pub mod json;
+pub mod nexus;
+pub mod signal_types;
 pub mod utils;
 
 use serde::{Deserialize, Serialize};
 use merge::Merge;
+use libflock::signals::{AudioSettings, MAX_BLOCK_SIZE, MAX_CHANNEL_COUNT};
+use std::{error::Error, fmt};
 
 // TODO: Replace this with an equivalent no-std alloc collection.
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-#[derive(Clone, Merge, Serialize, Deserialize, Eq, PartialEq, Debug)]
+#[derive(Clone, Default, Merge, Serialize, Deserialize, Eq, PartialEq, Debug)]
 pub struct EnvironmentSettings {
     pub host: Option<String>,
     pub input_device: Option<String>,
@@ -15,19 +20,464 @@ pub struct EnvironmentSettings {
     pub num_input_channels: Option<u32>,
     pub num_output_channels: Option<u32>,
     pub sample_rate: Option<u32>,
+    // The input device's sample rate,
+    // if it differs from the output device's.
+    pub input_sample_rate: Option<u32>,
     pub buffer_size: Option<u32>,
-    pub block_size: Option<u32>
+    pub block_size: Option<u32>,
+    // When strict, the environment fails to connect if the requested
+    // host or devices aren't available, or if the requested sample rate
+    // and channel counts aren't supported, instead of falling back.
+    pub strict: Option<bool>
 }
 
-#[derive(Serialize, Deserialize, Eq, PartialEq, Debug)]
-pub struct SignalSpec {}
+impl EnvironmentSettings {
+    // The settings that signals will be created with,
+    // using Flocking's defaults for anything unspecified.
+    pub fn audio_settings(&self) -> AudioSettings {
+        let block_size = self.block_size.unwrap_or(64) as usize;
+        let num_channels = self.num_output_channels.unwrap_or(2) as usize;
 
-#[derive(Serialize, Deserialize, Eq, PartialEq, Debug)]
-pub struct ConnectionSpec {}
+        AudioSettings {
+            sample_rate: self.sample_rate.unwrap_or(44100) as f32,
+            block_size: block_size.clamp(1, MAX_BLOCK_SIZE),
+            num_channels: num_channels.min(MAX_CHANNEL_COUNT)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Rate {
+    Audio,
+    // Control-rate signals generate one sample per block.
+    Control
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct SignalSpec {
+    // The name of a signal type in signal_types::SIGNAL_TYPES.
+    #[serde(rename = "type")]
+    pub signal_type: String,
+
+    // Constant values for the signal's inputs, keyed by input name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inputs: Option<HashMap<String, f32>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<HashMap<String, f32>>,
+
+    // Overrides the environment's block size for this signal.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_size: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate: Option<Rate>,
+
+    // Seeds noise signals, so that they generate the same output
+    // every time; otherwise they're seeded from the OS's randomness.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>
+}
+
+impl SignalSpec {
+    pub fn new(signal_type: &str) -> SignalSpec {
+        SignalSpec {
+            signal_type: signal_type.to_string(),
+            inputs: None,
+            parameters: None,
+            block_size: None,
+            rate: None,
+            seed: None
+        }
+    }
+}
+
+// Connects an output channel of the source signal
+// to a named input of the target signal.
+#[derive(Clone, Serialize, Deserialize, Eq, PartialEq, Debug)]
+pub struct ConnectionSpec {
+    pub source: String,
+
+    // The source's output channel; defaults to the first channel.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<u32>,
+
+    pub target: String,
+    pub input: String,
+
+    // How many samples the input advances per sample of output.
+    // Defaults to 1 (audio rate); 0 reads only the first sample
+    // of each block (control rate).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step_size: Option<u32>
+}
+
+impl ConnectionSpec {
+    pub fn new(source: &str, target: &str, input: &str) -> ConnectionSpec {
+        ConnectionSpec {
+            source: source.to_string(),
+            channel: None,
+            target: target.to_string(),
+            input: input.to_string(),
+            step_size: None
+        }
+    }
+}
+
+// Maps an external control, such as an OSC address,
+// to one of a signal's inputs or parameters.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct ControlSpec {
+    pub signal: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameter: Option<String>,
+
+    // Scales incoming values, e.g. from a fader's 0..1 range
+    // to a range of frequencies.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mul: Option<f32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub add: Option<f32>
+}
+
+impl ControlSpec {
+    pub fn scale(&self, value: f32) -> f32 {
+        value * self.mul.unwrap_or(1.0) + self.add.unwrap_or(0.0)
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Debug)]
+pub struct OscSpec {
+    // The UDP port to listen on.
+    pub port: Option<u16>,
+
+    // Custom addresses, in addition to the /signal/<id>/<name>
+    // addresses that are always available.
+    pub addresses: Option<HashMap<String, ControlSpec>>
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MidiMessageType {
+    // The frequency, in Hz, of the most recently held note.
+    Frequency,
+    // The velocity of the most recent note, from 0 to 1.
+    Velocity,
+    // 1 while any note is held and 0 once they've all been released.
+    Gate,
+    // The value of a controller, from 0 to 1.
+    Control,
+    // From -1 to 1.
+    PitchBend
+}
+
+// Maps MIDI messages to one of a signal's inputs or parameters.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct MidiControlSpec {
+    pub message: MidiMessageType,
+
+    // The controller number of control messages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub number: Option<u8>,
+
+    // The channel to listen to, from 0 to 15; defaults to all channels.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<u8>,
 
-#[derive(Serialize, Deserialize, Eq, PartialEq, Debug)]
+    #[serde(flatten)]
+    pub control: ControlSpec
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Debug)]
+pub struct MidiSpec {
+    // The raw MIDI device to read from, e.g. /dev/snd/midiC1D0.
+    pub device: Option<String>,
+    pub controls: Option<HashMap<String, MidiControlSpec>>
+}
+
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug)]
 pub struct CompositionSpec {
     pub environment: EnvironmentSettings,
     pub signals: Option<HashMap<String, SignalSpec>>,
-    pub connections: Option<HashMap<String, ConnectionSpec>>
+    pub connections: Option<HashMap<String, ConnectionSpec>>,
+    // The ID of the signal whose output is played by the environment.
+    pub output: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub osc: Option<OscSpec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub midi: Option<MidiSpec>
+}
+
+// Describes a problem with a composition,
+// identifying the offending signal by its ID.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SpecError {
+    UnknownSignalType { signal: String, signal_type: String },
+    UnknownInput { signal: String, input: String },
+    UnknownParameter { signal: String, parameter: String },
+    InvalidBlockSize { signal: String, block_size: u32 },
+    // The environment's block size must be between 1 and MAX_BLOCK_SIZE.
+    InvalidEnvironmentBlockSize { block_size: u32 },
+    UnknownSignal { connection: String, signal: String },
+    DuplicateConnection { signal: String, input: String },
+    UnknownOutput { signal: String },
+    UnknownChannel { signal: String, channel: u32 },
+    UnknownControlSignal { control: String, signal: String },
+    // Controls must map to exactly one input or parameter.
+    InvalidControl { control: String },
+    // MIDI control messages must name a controller number.
+    InvalidMidiControl { control: String }
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpecError::UnknownSignalType { signal, signal_type } =>
+                write!(f, "Signal {} has an unknown type: {}.",
+                    signal, signal_type),
+            SpecError::UnknownInput { signal, input } =>
+                write!(f, "Signal {} has no input named {}.",
+                    signal, input),
+            SpecError::UnknownParameter { signal, parameter } =>
+                write!(f, "Signal {} has no parameter named {}.",
+                    signal, parameter),
+            SpecError::InvalidBlockSize { signal, block_size } =>
+                write!(f, "Signal {} has an invalid block size: {}.",
+                    signal, block_size),
+            SpecError::InvalidEnvironmentBlockSize { block_size } =>
+                write!(f, "The environment's block size must be between \
+                    1 and {}, not {}.", MAX_BLOCK_SIZE, block_size),
+            SpecError::UnknownSignal { connection, signal } =>
+                write!(f, "Connection {} refers to an unknown signal: {}.",
+                    connection, signal),
+            SpecError::DuplicateConnection { signal, input } =>
+                write!(f, "Input {} of signal {} has more than one connection.",
+                    input, signal),
+            SpecError::UnknownOutput { signal } =>
+                write!(f, "The output refers to an unknown signal: {}.",
+                    signal),
+            SpecError::UnknownChannel { signal, channel } =>
+                write!(f, "Signal {} has no output channel {}.",
+                    signal, channel),
+            SpecError::UnknownControlSignal { control, signal } =>
+                write!(f, "Control {} refers to an unknown signal: {}.",
+                    control, signal),
+            SpecError::InvalidControl { control } =>
+                write!(f, "Control {} must name either an input \
+                    or a parameter.", control),
+            SpecError::InvalidMidiControl { control } =>
+                write!(f, "MIDI control {} must have a channel below 16 \
+                    and, for control messages, a number below 128.", control)
+        }
+    }
+}
+
+impl Error for SpecError {}
+
+impl SignalSpec {
+    pub fn validate(&self, id: &str) -> Result<(), SpecError> {
+        let signal_type = signal_types::find(&self.signal_type).ok_or_else(||
+            SpecError::UnknownSignalType {
+                signal: id.to_string(),
+                signal_type: self.signal_type.clone()
+            })?;
+
+        for input in self.inputs.iter().flat_map(|inputs| inputs.keys()) {
+            if signal_type.input_index(input).is_none() {
+                return Err(SpecError::UnknownInput {
+                    signal: id.to_string(),
+                    input: input.clone()
+                })
+            }
+        }
+
+        for parameter in self.parameters.iter()
+            .flat_map(|parameters| parameters.keys()) {
+            if signal_type.parameter_index(parameter).is_none() {
+                return Err(SpecError::UnknownParameter {
+                    signal: id.to_string(),
+                    parameter: parameter.clone()
+                })
+            }
+        }
+
+        if let Some(block_size) = self.block_size {
+            if block_size == 0 || block_size as usize > MAX_BLOCK_SIZE {
+                return Err(SpecError::InvalidBlockSize {
+                    signal: id.to_string(),
+                    block_size
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    // A signal's own block size has to divide the environment's,
+    // since it's generated several times per block. AudioIn signals
+    // read whole blocks from the input device, so can't be split.
+    pub fn validate_block_size(&self, id: &str, environment_block_size: usize)
+        -> Result<(), SpecError> {
+        match (self.rate, self.block_size) {
+            (Some(Rate::Control), _) | (_, None) => Ok(()),
+            (_, Some(block_size)) if self.signal_type == "audio_in" &&
+                block_size as usize != environment_block_size =>
+                Err(SpecError::InvalidBlockSize {
+                    signal: id.to_string(),
+                    block_size
+                }),
+            (_, Some(block_size)) if block_size > 0 &&
+                environment_block_size.is_multiple_of(block_size as usize) =>
+                Ok(()),
+            (_, Some(block_size)) => Err(SpecError::InvalidBlockSize {
+                signal: id.to_string(),
+                block_size
+            })
+        }
+    }
+}
+
+impl ControlSpec {
+    pub fn validate(
+        &self,
+        control: &str,
+        signals: &HashMap<String, SignalSpec>
+    ) -> Result<(), SpecError> {
+        let target = signals.get(&self.signal).ok_or_else(||
+            SpecError::UnknownControlSignal {
+                control: control.to_string(),
+                signal: self.signal.clone()
+            })?;
+        let signal_type = signal_types::find(&target.signal_type)
+            .ok_or_else(|| SpecError::UnknownSignalType {
+                signal: self.signal.clone(),
+                signal_type: target.signal_type.clone()
+            })?;
+
+        match (&self.input, &self.parameter) {
+            (Some(input), None) => match signal_type.input_index(input) {
+                Some(_) => Ok(()),
+                None => Err(SpecError::UnknownInput {
+                    signal: self.signal.clone(),
+                    input: input.clone()
+                })
+            },
+            (None, Some(parameter)) =>
+                match signal_type.parameter_index(parameter) {
+                Some(_) => Ok(()),
+                None => Err(SpecError::UnknownParameter {
+                    signal: self.signal.clone(),
+                    parameter: parameter.clone()
+                })
+            },
+            _ => Err(SpecError::InvalidControl {
+                control: control.to_string()
+            })
+        }
+    }
+}
+
+impl MidiControlSpec {
+    pub fn validate(
+        &self,
+        control: &str,
+        signals: &HashMap<String, SignalSpec>
+    ) -> Result<(), SpecError> {
+        let has_valid_number = match self.message {
+            MidiMessageType::Control =>
+                self.number.is_some_and(|number| number < 128),
+            _ => true
+        };
+        let has_valid_channel = self.channel.is_none_or(|channel|
+            channel < 16);
+
+        if !has_valid_number || !has_valid_channel {
+            return Err(SpecError::InvalidMidiControl {
+                control: control.to_string()
+            })
+        }
+
+        self.control.validate(control, signals)
+    }
+}
+
+impl CompositionSpec {
+    pub fn validate(&self) -> Result<(), SpecError> {
+        let empty = HashMap::new();
+        let signals = self.signals.as_ref().unwrap_or(&empty);
+
+        if let Some(output) = &self.output {
+            if !signals.contains_key(output) {
+                return Err(SpecError::UnknownOutput {
+                    signal: output.clone()
+                })
+            }
+        }
+
+        if let Some(block_size) = self.environment.block_size {
+            if block_size == 0 || block_size as usize > MAX_BLOCK_SIZE {
+                return Err(SpecError::InvalidEnvironmentBlockSize {
+                    block_size
+                })
+            }
+        }
+
+        let block_size = self.environment.audio_settings().block_size;
+        for (id, signal) in signals {
+            signal.validate(id)?;
+            signal.validate_block_size(id, block_size)?;
+        }
+
+        let mut connected_inputs = HashSet::new();
+        for (id, connection) in self.connections.iter().flatten() {
+            let unknown_signal = |signal: &str| SpecError::UnknownSignal {
+                connection: id.clone(),
+                signal: signal.to_string()
+            };
+
+            if !signals.contains_key(&connection.source) {
+                return Err(unknown_signal(&connection.source))
+            }
+
+            let target = signals.get(&connection.target)
+                .ok_or_else(|| unknown_signal(&connection.target))?;
+            let has_input = signal_types::find(&target.signal_type)
+                .and_then(|signal_type|
+                    signal_type.input_index(&connection.input))
+                .is_some();
+            if !has_input {
+                return Err(SpecError::UnknownInput {
+                    signal: connection.target.clone(),
+                    input: connection.input.clone()
+                })
+            }
+
+            if !connected_inputs.insert((&connection.target, &connection.input)) {
+                return Err(SpecError::DuplicateConnection {
+                    signal: connection.target.clone(),
+                    input: connection.input.clone()
+                })
+            }
+        }
+
+        let addresses = self.osc.iter()
+            .flat_map(|osc| osc.addresses.iter().flatten());
+        for (address, control) in addresses {
+            control.validate(address, signals)?;
+        }
+
+        let controls = self.midi.iter()
+            .flat_map(|midi| midi.controls.iter().flatten());
+        for (id, control) in controls {
+            control.validate(id, signals)?;
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,662 @@
+use super::*;
+use crate::builder::{
+    self, BuildError, CommandQueue, Composition, EventQueue
+};
+use libflock::evaluator::{
+    Command, EvaluatorError, Event, SignalId, MAX_SIGNALS
+};
+use libflock::input::AudioInput;
+use libflock::signals::Signal;
+use std::sync::Arc;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum NexusError {
+    Invalid(SpecError),
+    DuplicateSignal(String),
+    UnknownSignal(String),
+    DuplicateConnection(String),
+    UnknownConnection(String),
+    // The signal's input is connected to another signal,
+    // so it can't be set to a constant value.
+    InputConnected(String, String),
+    TooManySignals,
+    // The graph isn't applying commands,
+    // e.g. because its stream has stopped.
+    QueueFull
+}
+
+impl fmt::Display for NexusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NexusError::Invalid(e) => write!(f, "{}", e),
+            NexusError::DuplicateSignal(id) =>
+                write!(f, "There is already a signal named {}.", id),
+            NexusError::UnknownSignal(id) =>
+                write!(f, "There is no signal named {}.", id),
+            NexusError::DuplicateConnection(id) =>
+                write!(f, "There is already a connection named {}.", id),
+            NexusError::UnknownConnection(id) =>
+                write!(f, "There is no connection named {}.", id),
+            NexusError::InputConnected(signal, input) =>
+                write!(f, "The {} input of {} is connected to another signal.",
+                    input, signal),
+            NexusError::TooManySignals =>
+                write!(f, "The graph can't hold more than {} signals.",
+                    MAX_SIGNALS),
+            NexusError::QueueFull =>
+                write!(f, "The graph isn't accepting commands.")
+        }
+    }
+}
+
+impl Error for NexusError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NexusError::Invalid(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<SpecError> for NexusError {
+    fn from(e: SpecError) -> Self {
+        NexusError::Invalid(e)
+    }
+}
+
+// A change to a running composition. Changes can be sent to a Nexus
+// as JSON, e.g. by a remote client, and are reported to observers
+// once they've been made.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Change {
+    CreateSignal { id: String, spec: SignalSpec },
+    RemoveSignal { id: String },
+    SetInput { signal: String, input: String, value: f32 },
+    SetParameter { signal: String, parameter: String, value: f32 },
+    Connect { id: String, connection: ConnectionSpec },
+    Disconnect { id: String },
+    SetOutput { signal: String }
+}
+
+impl ControlSpec {
+    // The change that sets the control's input or parameter
+    // to the scaled value.
+    pub fn change(&self, value: f32) -> Option<Change> {
+        let signal = self.signal.clone();
+        let value = self.scale(value);
+
+        match (&self.input, &self.parameter) {
+            (Some(input), None) => Some(Change::SetInput {
+                signal,
+                input: input.clone(),
+                value
+            }),
+            (None, Some(parameter)) => Some(Change::SetParameter {
+                signal,
+                parameter: parameter.clone(),
+                value
+            }),
+            _ => None
+        }
+    }
+}
+
+// Creates, inspects, edits and deletes the signals of a running
+// Composition by their IDs (the keys of CompositionSpec.signals).
+// The Nexus lives on the main thread and sends its changes to the
+// Composition through a lock-free queue. It keeps a CompositionSpec
+// that describes the current state of the graph.
+pub struct Nexus {
+    spec: CompositionSpec,
+    settings: AudioSettings,
+    ids: HashMap<String, SignalId>,
+    // The number of output channels of each signal,
+    // so that connections to missing channels can be rejected
+    // before they reach the graph.
+    num_outputs: HashMap<String, usize>,
+    // The Composition's input, which new AudioIn signals read from.
+    input: Arc<AudioInput>,
+    commands: Arc<CommandQueue>,
+    events: Arc<EventQueue>,
+    // Commands that were rejected by the graph.
+    errors: Vec<EvaluatorError>
+}
+
+impl Nexus {
+    // Builds the composition and returns a Nexus that controls it,
+    // along with the Composition, which should be handed to the
+    // environment to be played.
+    pub fn new(spec: CompositionSpec, settings: AudioSettings) ->
+        Result<(Nexus, Composition), BuildError> {
+        let mut composition = builder::build(&spec, settings)?;
+        let commands = Arc::new(CommandQueue::new());
+        let events = Arc::new(EventQueue::new());
+        composition.attach(commands.clone(), events.clone());
+
+        let nexus = Nexus {
+            spec: CompositionSpec {
+                signals: Some(spec.signals.unwrap_or_default()),
+                connections: Some(spec.connections.unwrap_or_default()),
+                ..spec
+            },
+            settings,
+            ids: composition.ids().clone(),
+            num_outputs: composition.ids().keys()
+                .filter_map(|id| composition.signal(id)
+                    .map(|signal| (id.clone(), num_outputs(signal))))
+                .collect(),
+            input: composition.input().clone(),
+            commands,
+            events,
+            errors: Vec::new()
+        };
+
+        Ok((nexus, composition))
+    }
+
+    // The current state of the graph.
+    pub fn spec(&self) -> &CompositionSpec {
+        &self.spec
+    }
+
+    pub fn signal(&self, id: &str) -> Option<&SignalSpec> {
+        self.signals().get(id)
+    }
+
+    pub fn connection(&self, id: &str) -> Option<&ConnectionSpec> {
+        self.connections().get(id)
+    }
+
+    // Frees signals that the graph has released,
+    // and returns any errors the graph has reported since the last poll.
+    pub fn poll(&mut self) -> Vec<EvaluatorError> {
+        self.receive_events();
+
+        std::mem::take(&mut self.errors)
+    }
+
+    fn receive_events(&mut self) {
+        // The Nexus is the queue's only consumer of events.
+        let mut events = unsafe { self.events.consumer() };
+        while let Some(event) = events.pop() {
+            match event {
+                // Every signal in the graph was leaked from a Box.
+                Event::Removed(_, signal) => unsafe {
+                    drop(Box::from_raw(signal as *mut dyn Signal));
+                },
+                Event::Rejected(e) => self.errors.push(e)
+            }
+        }
+    }
+
+    pub fn create_signal(&mut self, id: &str, spec: SignalSpec) ->
+        Result<(), NexusError> {
+        if self.signals().contains_key(id) {
+            return Err(NexusError::DuplicateSignal(id.to_string()))
+        }
+        spec.validate(id)?;
+        spec.validate_block_size(id, self.settings.block_size)?;
+
+        let signal_id = (0..MAX_SIGNALS)
+            .find(|signal_id| !self.ids.values().any(|used| used == signal_id))
+            .ok_or(NexusError::TooManySignals)?;
+        let signal = builder::create_signal(&spec,
+            builder::signal_settings(&spec, self.settings), &self.input)
+            .ok_or_else(|| SpecError::UnknownSignalType {
+                signal: id.to_string(),
+                signal_type: spec.signal_type.clone()
+            })?;
+
+        let signal_outputs = num_outputs(&*signal);
+        self.send(Command::AddSignal {
+            id: signal_id,
+            signal: Box::leak(signal),
+            control_rate: spec.rate == Some(Rate::Control)
+        })?;
+
+        self.ids.insert(id.to_string(), signal_id);
+        self.num_outputs.insert(id.to_string(), signal_outputs);
+        self.signals_mut().insert(id.to_string(), spec);
+
+        Ok(())
+    }
+
+    // Removes the signal along with all of its connections.
+    // Inputs that it was connected to revert to their constant values.
+    pub fn remove_signal(&mut self, id: &str) -> Result<(), NexusError> {
+        let signal_id = self.signal_id(id)?;
+        self.send(Command::RemoveSignal(signal_id))?;
+
+        let mut connection_ids: Vec<String> = self.connections().iter()
+            .filter(|(_, c)| c.source == id || c.target == id)
+            .map(|(connection_id, _)| connection_id.clone())
+            .collect();
+        connection_ids.sort();
+        for connection_id in connection_ids {
+            let connection = self.connections_mut().remove(&connection_id);
+            if let Some(connection) = connection {
+                if connection.target != id {
+                    // If this fails, the input will have been
+                    // disconnected by the graph with a value of 0.
+                    let _ = self.reset_input(&connection);
+                }
+            }
+        }
+
+        self.ids.remove(id);
+        self.num_outputs.remove(id);
+        self.signals_mut().remove(id);
+        if self.spec.output.as_deref() == Some(id) {
+            self.spec.output = None;
+        }
+
+        Ok(())
+    }
+
+    pub fn connect(&mut self, id: &str, connection: ConnectionSpec) ->
+        Result<(), NexusError> {
+        if self.connections().contains_key(id) {
+            return Err(NexusError::DuplicateConnection(id.to_string()))
+        }
+
+        let source = self.signal(&connection.source).ok_or_else(||
+            NexusError::UnknownSignal(connection.source.clone()))?;
+        let target = self.signal(&connection.target).ok_or_else(||
+            NexusError::UnknownSignal(connection.target.clone()))?;
+        let input = builder::input_index(target, &connection)?;
+        let channel = connection.channel.unwrap_or(0);
+        if channel as usize >= self.num_outputs[&connection.source] {
+            return Err(NexusError::Invalid(SpecError::UnknownChannel {
+                signal: connection.source.clone(),
+                channel
+            }))
+        }
+
+        if self.connections().values().any(|c|
+            c.target == connection.target && c.input == connection.input) {
+            return Err(NexusError::Invalid(SpecError::DuplicateConnection {
+                signal: connection.target.clone(),
+                input: connection.input.clone()
+            }))
+        }
+
+        let step_size = builder::step_size(&connection, source);
+        self.send(Command::Connect {
+            source: self.signal_id(&connection.source)?,
+            channel: channel as usize,
+            target: self.signal_id(&connection.target)?,
+            input,
+            step_size
+        })?;
+
+        self.connections_mut().insert(id.to_string(), connection);
+
+        Ok(())
+    }
+
+    // Removes the connection, reverting the input it was connected to
+    // to its constant value.
+    pub fn disconnect(&mut self, id: &str) -> Result<(), NexusError> {
+        let connection = self.connection(id).cloned().ok_or_else(||
+            NexusError::UnknownConnection(id.to_string()))?;
+        self.reset_input(&connection)?;
+        self.connections_mut().remove(id);
+
+        Ok(())
+    }
+
+    // Sets the value of an input that isn't connected to another signal.
+    // Connected inputs must be disconnected first.
+    pub fn set_input(&mut self, signal: &str, input: &str, value: f32) ->
+        Result<(), NexusError> {
+        let target = self.signal(signal).ok_or_else(||
+            NexusError::UnknownSignal(signal.to_string()))?;
+        let index = signal_types::find(&target.signal_type)
+            .and_then(|signal_type| signal_type.input_index(input))
+            .ok_or_else(|| SpecError::UnknownInput {
+                signal: signal.to_string(),
+                input: input.to_string()
+            })?;
+
+        if self.connections().values().any(|c|
+            c.target == signal && c.input == input) {
+            return Err(NexusError::InputConnected(
+                signal.to_string(), input.to_string()))
+        }
+
+        self.send(Command::SetInputValue {
+            target: self.signal_id(signal)?,
+            input: index,
+            value
+        })?;
+
+        let spec = self.signals_mut().get_mut(signal).expect(
+            "The signal's spec exists");
+        spec.inputs.get_or_insert_with(HashMap::new)
+            .insert(input.to_string(), value);
+
+        Ok(())
+    }
+
+    pub fn set_parameter(&mut self, signal: &str, parameter: &str, value: f32)
+        -> Result<(), NexusError> {
+        let target = self.signal(signal).ok_or_else(||
+            NexusError::UnknownSignal(signal.to_string()))?;
+        let index = signal_types::find(&target.signal_type)
+            .and_then(|signal_type| signal_type.parameter_index(parameter))
+            .ok_or_else(|| SpecError::UnknownParameter {
+                signal: signal.to_string(),
+                parameter: parameter.to_string()
+            })?;
+
+        self.send(Command::SetParameter {
+            signal: self.signal_id(signal)?,
+            parameter: index,
+            value
+        })?;
+
+        let spec = self.signals_mut().get_mut(signal).expect(
+            "The signal's spec exists");
+        spec.parameters.get_or_insert_with(HashMap::new)
+            .insert(parameter.to_string(), value);
+
+        Ok(())
+    }
+
+    // Sets the signal's parameter or input with the specified name.
+    pub fn set_value(&mut self, signal: &str, name: &str, value: f32) ->
+        Result<(), NexusError> {
+        let target = self.signal(signal).ok_or_else(||
+            NexusError::UnknownSignal(signal.to_string()))?;
+        let is_parameter = signal_types::find(&target.signal_type)
+            .and_then(|signal_type| signal_type.parameter_index(name))
+            .is_some();
+
+        if is_parameter {
+            self.set_parameter(signal, name, value)
+        } else {
+            self.set_input(signal, name, value)
+        }
+    }
+
+    pub fn set_output(&mut self, signal: &str) -> Result<(), NexusError> {
+        self.send(Command::SetOutput(self.signal_id(signal)?))?;
+        self.spec.output = Some(signal.to_string());
+
+        Ok(())
+    }
+
+    pub fn apply(&mut self, change: Change) -> Result<(), NexusError> {
+        match change {
+            Change::CreateSignal { id, spec } => self.create_signal(&id, spec),
+            Change::RemoveSignal { id } => self.remove_signal(&id),
+            Change::SetInput { signal, input, value } =>
+                self.set_input(&signal, &input, value),
+            Change::SetParameter { signal, parameter, value } =>
+                self.set_parameter(&signal, &parameter, value),
+            Change::Connect { id, connection } =>
+                self.connect(&id, connection),
+            Change::Disconnect { id } => self.disconnect(&id),
+            Change::SetOutput { signal } => self.set_output(&signal)
+        }
+    }
+
+    fn signals(&self) -> &HashMap<String, SignalSpec> {
+        self.spec.signals.as_ref().expect("The Nexus always has signals")
+    }
+
+    fn signals_mut(&mut self) -> &mut HashMap<String, SignalSpec> {
+        self.spec.signals.get_or_insert_with(HashMap::new)
+    }
+
+    fn connections(&self) -> &HashMap<String, ConnectionSpec> {
+        self.spec.connections.as_ref()
+            .expect("The Nexus always has connections")
+    }
+
+    fn connections_mut(&mut self) -> &mut HashMap<String, ConnectionSpec> {
+        self.spec.connections.get_or_insert_with(HashMap::new)
+    }
+
+    fn signal_id(&self, id: &str) -> Result<SignalId, NexusError> {
+        self.ids.get(id).copied()
+            .ok_or_else(|| NexusError::UnknownSignal(id.to_string()))
+    }
+
+    // Reverts a connected input to the value in its signal's spec,
+    // or to the input's default value.
+    fn reset_input(&mut self, connection: &ConnectionSpec) ->
+        Result<(), NexusError> {
+        let target = match self.signal(&connection.target) {
+            Some(target) => target,
+            None => return Ok(())
+        };
+        let index = builder::input_index(target, connection)?;
+        let value = target.inputs.as_ref()
+            .and_then(|inputs| inputs.get(&connection.input)).copied()
+            .or_else(|| signal_types::find(&target.signal_type)
+                .and_then(|signal_type| signal_type.input_defaults.get(index))
+                .copied())
+            .unwrap_or(0.0);
+
+        self.send(Command::Disconnect {
+            target: self.signal_id(&connection.target)?,
+            input: index,
+            value
+        })
+    }
+
+    fn send(&mut self, command: Command<'static>) -> Result<(), NexusError> {
+        self.receive_events();
+
+        // The Nexus is the queue's only producer of commands.
+        let mut commands = unsafe { self.commands.producer() };
+        match commands.push(command) {
+            Ok(()) => Ok(()),
+            Err(Command::AddSignal { signal, .. }) => {
+                unsafe {
+                    drop(Box::from_raw(signal as *mut dyn Signal));
+                }
+                Err(NexusError::QueueFull)
+            },
+            Err(_) => Err(NexusError::QueueFull)
+        }
+    }
+}
+
+impl Drop for Nexus {
+    fn drop(&mut self) {
+        self.receive_events();
+    }
+}
+
+fn num_outputs(signal: &dyn Signal) -> usize {
+    (0..).map_while(|channel| signal.output(channel)).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libflock::evaluator::Graph;
+
+    fn empty_composition() -> CompositionSpec {
+        CompositionSpec {
+            environment: EnvironmentSettings {
+                sample_rate: Some(48000),
+                block_size: Some(16),
+                num_output_channels: Some(2),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn start() -> (Nexus, Composition) {
+        let spec = empty_composition();
+        let settings = spec.environment.audio_settings();
+        Nexus::new(spec, settings).unwrap()
+    }
+
+    #[test]
+    fn signals_are_created_and_connected_by_id() {
+        let (mut nexus, mut graph) = start();
+
+        nexus.create_signal("level", SignalSpec::new("value")).unwrap();
+        nexus.create_signal("out", SignalSpec::new("fan")).unwrap();
+        nexus.connect("speakers",
+            ConnectionSpec::new("level", "out", "source")).unwrap();
+        nexus.set_parameter("level", "value", 0.5).unwrap();
+        nexus.set_output("out").unwrap();
+
+        graph.evaluate();
+        assert_eq!(0.5, graph.output(1).unwrap()[15]);
+        assert!(nexus.poll().is_empty());
+
+        // The spec describes the graph as it is now,
+        // and can be used to build an equivalent graph.
+        let spec = nexus.spec();
+        assert_eq!(Some(&0.5), spec.signals.as_ref().unwrap()["level"]
+            .parameters.as_ref().unwrap().get("value"));
+        assert_eq!(Some("out".to_string()), spec.output);
+
+        let json = json::to_json(spec).unwrap();
+        let mut rebuilt = builder::build(&json::parse_composition(&json)
+            .unwrap(), spec.environment.audio_settings()).unwrap();
+        rebuilt.evaluate();
+        assert_eq!(0.5, rebuilt.output(1).unwrap()[15]);
+    }
+
+    #[test]
+    fn removing_a_signal_reverts_its_connections() {
+        let (mut nexus, mut graph) = start();
+
+        let mut sine = SignalSpec::new("sine");
+        sine.inputs = Some([("mul".to_string(), 0.0),
+            ("add".to_string(), 0.25)].into());
+        nexus.create_signal("offset", sine).unwrap();
+        nexus.create_signal("out", SignalSpec::new("fan")).unwrap();
+        nexus.connect("speakers",
+            ConnectionSpec::new("offset", "out", "source")).unwrap();
+        assert_eq!(Err(NexusError::InputConnected(
+                "out".to_string(), "source".to_string())),
+            nexus.set_input("out", "source", 0.75));
+        assert!(nexus.connection("speakers").is_some(),
+            "Connected inputs are left alone");
+
+        nexus.disconnect("speakers").unwrap();
+        nexus.set_input("out", "source", 0.75).unwrap();
+        nexus.connect("speakers",
+            ConnectionSpec::new("offset", "out", "source")).unwrap();
+        nexus.set_output("out").unwrap();
+        graph.evaluate();
+        assert_eq!(0.25, graph.output(0).unwrap()[0]);
+
+        nexus.remove_signal("offset").unwrap();
+        graph.evaluate();
+        assert!(nexus.poll().is_empty());
+
+        assert!(nexus.signal("offset").is_none());
+        assert!(nexus.spec().connections.as_ref().unwrap().is_empty());
+        assert_eq!(0.75, graph.output(0).unwrap()[0],
+            "The Fan's input reverted to the value in its spec");
+    }
+
+    #[test]
+    fn invalid_changes_are_rejected() {
+        let (mut nexus, _graph) = start();
+
+        nexus.create_signal("a", SignalSpec::new("sine")).unwrap();
+        nexus.create_signal("b", SignalSpec::new("sine")).unwrap();
+        assert_eq!(Err(NexusError::DuplicateSignal("a".to_string())),
+            nexus.create_signal("a", SignalSpec::new("fan")));
+        assert!(matches!(
+            nexus.create_signal("c", SignalSpec::new("sawtooth")),
+            Err(NexusError::Invalid(SpecError::UnknownSignalType { .. }))));
+
+        nexus.connect("ab", ConnectionSpec::new("a", "b", "freq")).unwrap();
+        assert!(matches!(
+            nexus.connect("ab", ConnectionSpec::new("b", "a", "freq")),
+            Err(NexusError::DuplicateConnection(_))));
+        assert_eq!(Err(NexusError::UnknownSignal("z".to_string())),
+            nexus.connect("za", ConnectionSpec::new("z", "a", "freq")));
+        assert_eq!(Err(NexusError::UnknownConnection("ba".to_string())),
+            nexus.disconnect("ba"));
+        assert!(matches!(nexus.set_parameter("a", "value", 1.0),
+            Err(NexusError::Invalid(SpecError::UnknownParameter { .. }))));
+
+        let mut stereo = ConnectionSpec::new("a", "b", "phase_offset");
+        stereo.channel = Some(1);
+        assert_eq!(Err(NexusError::Invalid(SpecError::UnknownChannel {
+            signal: "a".to_string(),
+            channel: 1
+        })), nexus.connect("ab_right", stereo));
+        assert!(nexus.connection("ab_right").is_none(),
+            "Rejected connections aren't added to the spec");
+    }
+
+    #[test]
+    fn connections_that_close_a_cycle_are_delayed_by_a_block() {
+        let (mut nexus, mut graph) = start();
+
+        // The feedback source is mixed into the same channel,
+        // so the output accumulates the level once per block.
+        let mut mix = SignalSpec::new("audio_out");
+        mix.parameters = Some([("channel_1".to_string(), 0.0)].into());
+        let mut level = SignalSpec::new("value");
+        level.parameters = Some([("value".to_string(), 0.25)].into());
+        nexus.create_signal("level", level).unwrap();
+        nexus.create_signal("mix", mix).unwrap();
+        nexus.create_signal("feedback", SignalSpec::new("fan")).unwrap();
+        nexus.connect("dry",
+            ConnectionSpec::new("level", "mix", "source_0")).unwrap();
+        nexus.connect("send",
+            ConnectionSpec::new("mix", "feedback", "source")).unwrap();
+        nexus.connect("return",
+            ConnectionSpec::new("feedback", "mix", "source_1")).unwrap();
+        nexus.set_output("mix").unwrap();
+
+        for expected in [0.25, 0.5, 0.75] {
+            graph.evaluate();
+            assert_eq!([expected; 16], graph.output(0).unwrap()[0..16]);
+        }
+        assert!(nexus.poll().is_empty(), "The cycle wasn't rejected");
+    }
+
+    #[test]
+    fn changes_are_applied_from_json() {
+        let (mut nexus, mut graph) = start();
+
+        let changes = r#"[
+            { "op": "create_signal", "id": "level",
+                "spec": { "type": "value" } },
+            { "op": "set_parameter", "signal": "level",
+                "parameter": "value", "value": 0.125 },
+            { "op": "set_output", "signal": "level" }
+        ]"#;
+        for change in serde_json::from_str::<Vec<Change>>(changes).unwrap() {
+            nexus.apply(change).unwrap();
+        }
+
+        graph.evaluate();
+        assert_eq!(0.125, graph.output(0).unwrap()[0]);
+        assert_eq!(Err(NexusError::UnknownSignal("osc".to_string())),
+            nexus.apply(Change::RemoveSignal { id: "osc".to_string() }));
+    }
+
+    #[test]
+    fn commands_wait_for_the_graph() {
+        let (mut nexus, mut graph) = start();
+        nexus.create_signal("level", SignalSpec::new("value")).unwrap();
+
+        let mut result = Ok(());
+        for i in 0..builder::QUEUE_SIZE {
+            result = nexus.set_parameter("level", "value", i as f32);
+        }
+        assert_eq!(Err(NexusError::QueueFull), result,
+            "The queue fills up when the graph isn't running");
+
+        graph.evaluate();
+        nexus.set_parameter("level", "value", 1.0).unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+// Describes the signal types that can be declared in a composition.
+// Inputs and parameters are listed in the same order
+// as the indices used by the corresponding libflock signals.
+#[derive(Debug, PartialEq)]
+pub struct SignalType {
+    pub name: &'static str,
+    pub inputs: &'static [&'static str],
+    // The constant value of each input when it isn't specified.
+    pub input_defaults: &'static [f32],
+    pub parameters: &'static [&'static str]
+}
+
+impl SignalType {
+    pub fn input_index(&self, name: &str) -> Option<usize> {
+        self.inputs.iter().position(|input| *input == name)
+    }
+
+    pub fn parameter_index(&self, name: &str) -> Option<usize> {
+        self.parameters.iter().position(|parameter| *parameter == name)
+    }
+}
+
+// Every filter response has the same inputs.
+const FILTER_INPUTS: &[&str] = &["source", "cutoff", "q", "gain", "mul", "add"];
+const FILTER_INPUT_DEFAULTS: &[f32] = &[
+    0.0, 1000.0, core::f32::consts::FRAC_1_SQRT_2, 0.0, 1.0, 0.0
+];
+
+pub const SIGNAL_TYPES: &[SignalType] = &[
+    SignalType {
+        name: "value",
+        inputs: &[],
+        input_defaults: &[],
+        parameters: &["value"]
+    },
+    SignalType {
+        name: "sine",
+        inputs: &["freq", "phase_offset", "mul", "add"],
+        input_defaults: &[440.0, 0.0, 1.0, 0.0],
+        parameters: &[]
+    },
+    SignalType {
+        name: "saw",
+        inputs: &["freq", "phase_offset", "mul", "add"],
+        input_defaults: &[440.0, 0.0, 1.0, 0.0],
+        parameters: &[]
+    },
+    SignalType {
+        name: "square",
+        inputs: &["freq", "phase_offset", "mul", "add"],
+        input_defaults: &[440.0, 0.0, 1.0, 0.0],
+        parameters: &[]
+    },
+    SignalType {
+        name: "triangle",
+        inputs: &["freq", "phase_offset", "mul", "add"],
+        input_defaults: &[440.0, 0.0, 1.0, 0.0],
+        parameters: &[]
+    },
+    SignalType {
+        name: "pulse",
+        inputs: &["freq", "phase_offset", "mul", "add", "width"],
+        input_defaults: &[440.0, 0.0, 1.0, 0.0, 0.5],
+        parameters: &[]
+    },
+    SignalType {
+        name: "white_noise",
+        inputs: &["mul", "add"],
+        input_defaults: &[1.0, 0.0],
+        parameters: &[]
+    },
+    SignalType {
+        name: "pink_noise",
+        inputs: &["mul", "add"],
+        input_defaults: &[1.0, 0.0],
+        parameters: &[]
+    },
+    SignalType {
+        name: "brown_noise",
+        inputs: &["mul", "add"],
+        input_defaults: &[1.0, 0.0],
+        parameters: &[]
+    },
+    SignalType {
+        name: "adsr",
+        inputs: &["gate", "mul", "add"],
+        input_defaults: &[0.0, 1.0, 0.0],
+        parameters: &["attack", "attack_level", "attack_curve",
+            "decay", "sustain", "decay_curve",
+            "release", "release_level", "release_curve"]
+    },
+    SignalType {
+        name: "low_pass",
+        inputs: FILTER_INPUTS,
+        input_defaults: FILTER_INPUT_DEFAULTS,
+        parameters: &[]
+    },
+    SignalType {
+        name: "high_pass",
+        inputs: FILTER_INPUTS,
+        input_defaults: FILTER_INPUT_DEFAULTS,
+        parameters: &[]
+    },
+    SignalType {
+        name: "band_pass",
+        inputs: FILTER_INPUTS,
+        input_defaults: FILTER_INPUT_DEFAULTS,
+        parameters: &[]
+    },
+    SignalType {
+        name: "notch",
+        inputs: FILTER_INPUTS,
+        input_defaults: FILTER_INPUT_DEFAULTS,
+        parameters: &[]
+    },
+    SignalType {
+        name: "peaking",
+        inputs: FILTER_INPUTS,
+        input_defaults: FILTER_INPUT_DEFAULTS,
+        parameters: &[]
+    },
+    SignalType {
+        name: "low_shelf",
+        inputs: FILTER_INPUTS,
+        input_defaults: FILTER_INPUT_DEFAULTS,
+        parameters: &[]
+    },
+    SignalType {
+        name: "high_shelf",
+        inputs: FILTER_INPUTS,
+        input_defaults: FILTER_INPUT_DEFAULTS,
+        parameters: &[]
+    },
+    SignalType {
+        name: "fan",
+        inputs: &["source"],
+        input_defaults: &[0.0],
+        parameters: &[]
+    },
+    SignalType {
+        name: "audio_out",
+        inputs: &["source_0", "source_1", "source_2", "source_3",
+            "source_4", "source_5", "source_6", "source_7"],
+        input_defaults: &[0.0; 8],
+        parameters: &["channel_0", "channel_1", "channel_2", "channel_3",
+            "channel_4", "channel_5", "channel_6", "channel_7"]
+    },
+    SignalType {
+        name: "audio_in",
+        inputs: &[],
+        input_defaults: &[],
+        parameters: &[]
+    }
+];
+
+pub fn find(name: &str) -> Option<&'static SignalType> {
+    SIGNAL_TYPES.iter().find(|signal_type| signal_type.name == name)
+}
@@ -11,12 +11,10 @@ pub fn merge_options<T>(defaults: &T, options: Option<&T>) -> T
         Some(o) => {
             let mut left = o.clone();
             left.merge(right);
-            return left
+            left
         },
 
-        None => {
-            return right
-        }
+        None => right
     }
 }
 
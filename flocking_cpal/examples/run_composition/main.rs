@@ -1,5 +1,5 @@
-use flocking;
-use std::{fs, env, process, error::Error};
+use std::{fs, env, io, process, thread, error::Error};
+use std::sync::{Arc, Mutex};
 
 extern crate flocking_cpal;
 
@@ -8,26 +8,106 @@ fn run(composition_file_path: String) -> Result<(), Box<dyn Error>> {
 
     let composition_spec = flocking::json::parse_composition(&compostion_json)?;
 
-    let environment = flocking_cpal::env::Environment::new(composition_spec.environment);
+    let environment = flocking_cpal::env::Environment::new(
+        composition_spec.environment.clone())?;
 
     println!("Selected host: {:?}", environment.host.id());
 
-    if let Some(output_device) = environment.host_audio.output {
+    if let Some(output_device) = &environment.host_audio.output {
         println!("Selected output device: {}",
-            flocking_cpal::utils::device_display_name(&output_device));
+            flocking_cpal::utils::device_display_name(output_device));
     }
 
-    if let Some(input_device) = environment.host_audio.input {
+    if let Some(input_device) = &environment.host_audio.input {
         println!("Selected input device: {}",
-        flocking_cpal::utils::device_display_name(&input_device));
+        flocking_cpal::utils::device_display_name(input_device));
     }
 
     println!("{:?}", environment.settings);
 
+    // The streams stop playing when the connections are dropped.
+    let osc_spec = composition_spec.osc.clone();
+    let midi_spec = composition_spec.midi.clone();
+    let mut nexus = None;
+    let connections = environment.connect(|settings| {
+        flocking::nexus::Nexus::new(composition_spec, settings)
+            .map(|(n, composition)| {
+                nexus = Some(Arc::new(Mutex::new(n)));
+                composition
+            })
+    }, |e| eprintln!("An error occurred on a stream: {}", e))?;
+
+    if let Some(output) = &connections.output {
+        println!("Output stream: {}", output.config);
+    }
+
+    if let Some(input) = &connections.input {
+        println!("Input stream: {}", input.config);
+    }
+
+    println!("Connected: {:?}", connections.settings);
+    if let Some(e) = &connections.input_error {
+        eprintln!("The input device couldn't be opened. {}", e);
+    }
+
+    let osc = match (&nexus, osc_spec) {
+        (Some(nexus), Some(osc_spec)) => {
+            let port = osc_spec.port
+                .unwrap_or(flocking_cpal::osc::DEFAULT_PORT);
+            let listener = flocking_cpal::osc::OscListener::start(
+                nexus.clone(), ("0.0.0.0", port), &osc_spec,
+                |e| eprintln!("Ignoring OSC message: {}", e))?;
+            println!("OSC listening on {}", listener.address());
+            Some(listener)
+        },
+        _ => None
+    };
+
+    if let (Some(nexus), Some(midi_spec)) = (&nexus, midi_spec) {
+        if let Some(device) = &midi_spec.device {
+            let device = fs::File::open(device)?;
+            let listener = flocking_cpal::midi::MidiListener::start(
+                nexus.clone(), device, &midi_spec,
+                |e| eprintln!("Ignoring MIDI message: {}", e));
+            println!("MIDI listening to {:?}", midi_spec.device);
+
+            thread::spawn(move || {
+                if let Err(e) = listener.join() {
+                    eprintln!("The MIDI listener failed: {}", e);
+                }
+            });
+        }
+    }
+
+    #[cfg(feature = "server")]
+    let _server = match nexus.take() {
+        Some(nexus) => {
+            let server = flocking_cpal::server::NexusServer::start(
+                nexus, flocking_cpal::server::DEFAULT_ADDRESS,
+                |e| eprintln!("Nexus server connection failed: {}", e))?;
+            println!("Nexus server listening on http://{}", server.address());
+            Some(server)
+        },
+        None => None
+    };
+
+    // Without a server, only OSC and MIDI messages
+    // change the composition once it's playing.
+    #[cfg(not(feature = "server"))]
+    let _nexus = nexus;
+
+    println!("Playing. Press enter to stop.");
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+
+    if let Some(Err(e)) = osc.map(|listener| listener.stop()) {
+        eprintln!("The OSC listener failed: {}", e);
+    }
+
     Ok(())
 }
 
-fn check_arguments(args: &Vec<String>) {
+fn check_arguments(args: &[String]) {
     if args.len() < 2 {
         println!("Error: No composition file was specified as an argument.");
         process::exit(1);
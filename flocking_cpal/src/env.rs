@@ -1,12 +1,97 @@
 // TODO: Export Environment to the root of flocking_cpal.
 
+use std::{error::Error, fmt};
+use std::sync::Arc;
 use flocking::EnvironmentSettings;
-use cpal::Host;
+use flocking::builder::Composition;
+use crate::utils::device_display_name;
+use libflock::evaluator::Graph;
+use libflock::input::AudioInput;
+use libflock::signals::{AudioSettings, MAX_CHANNEL_COUNT};
+use cpal::{Host, Sample, SampleFormat};
 use cpal::traits::DeviceTrait;
 use cpal::traits::HostTrait;
+use cpal::traits::StreamTrait;
 
-pub fn find_host(settings: &EnvironmentSettings) -> cpal::Host {
-    let host: cpal::Host = match &settings.host {
+#[derive(Debug)]
+pub enum EnvironmentError {
+    // The requested host isn't available on this platform.
+    HostNotFound(String),
+    HostUnavailable(cpal::HostUnavailable),
+    // The requested device (or, if None, a default device)
+    // isn't available.
+    DeviceNotFound(Option<String>),
+    // The device doesn't support any stream configuration
+    // that matches the requested settings.
+    NoMatchingConfig(String),
+    BuildStream(cpal::BuildStreamError),
+    PlayStream(cpal::PlayStreamError),
+    // The graph to be played couldn't be built.
+    Graph(Box<dyn Error + Send + Sync>)
+}
+
+impl fmt::Display for EnvironmentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EnvironmentError::HostNotFound(name) =>
+                write!(f, "The {} host is not available.", name),
+            EnvironmentError::HostUnavailable(e) =>
+                write!(f, "The host is unavailable. {}", e),
+            EnvironmentError::DeviceNotFound(Some(name)) =>
+                write!(f, "The device {} was not found.", name),
+            EnvironmentError::DeviceNotFound(None) =>
+                write!(f, "No default device is available."),
+            EnvironmentError::NoMatchingConfig(device_name) =>
+                write!(f, "The device {} doesn't support the requested \
+                    stream configuration.", device_name),
+            EnvironmentError::BuildStream(e) =>
+                write!(f, "Unable to open the stream. {}", e),
+            EnvironmentError::PlayStream(e) =>
+                write!(f, "Unable to play the stream. {}", e),
+            EnvironmentError::Graph(e) =>
+                write!(f, "Unable to build the graph. {}", e)
+        }
+    }
+}
+
+impl Error for EnvironmentError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EnvironmentError::HostUnavailable(e) => Some(e),
+            EnvironmentError::BuildStream(e) => Some(e),
+            EnvironmentError::PlayStream(e) => Some(e),
+            EnvironmentError::Graph(e) => Some(e.as_ref()),
+            _ => None
+        }
+    }
+}
+
+impl From<cpal::HostUnavailable> for EnvironmentError {
+    fn from(e: cpal::HostUnavailable) -> Self {
+        EnvironmentError::HostUnavailable(e)
+    }
+}
+
+impl From<cpal::BuildStreamError> for EnvironmentError {
+    fn from(e: cpal::BuildStreamError) -> Self {
+        EnvironmentError::BuildStream(e)
+    }
+}
+
+impl From<cpal::PlayStreamError> for EnvironmentError {
+    fn from(e: cpal::PlayStreamError) -> Self {
+        EnvironmentError::PlayStream(e)
+    }
+}
+
+pub fn is_strict(settings: &EnvironmentSettings) -> bool {
+    settings.strict.unwrap_or(false)
+}
+
+pub fn find_host(
+    settings: &EnvironmentSettings
+) -> Result<cpal::Host, EnvironmentError> {
+    match &settings.host {
         Some(host_name) => {
             let host_ids = cpal::available_hosts();
             let mut host_iter = host_ids.iter();
@@ -14,20 +99,19 @@ pub fn find_host(settings: &EnvironmentSettings) -> cpal::Host {
             if let Some(matched_id) = host_iter.find(
                 |&host_id| host_id.name().to_lowercase() ==
                 host_name.to_lowercase()) {
-                // TODO: Properly handle this error.
-                cpal::host_from_id(*matched_id).unwrap()
+                Ok(cpal::host_from_id(*matched_id)?)
+            } else if is_strict(settings) {
+                Err(EnvironmentError::HostNotFound(host_name.clone()))
             } else {
-                cpal::default_host()
+                Ok(cpal::default_host())
             }
         },
 
-        None => cpal::default_host()
-    };
-
-    host
+        None => Ok(cpal::default_host())
+    }
 }
 
-pub fn match_device_name(requested_name: &String, device: &cpal::Device) -> bool {
+pub fn match_device_name(requested_name: &str, device: &cpal::Device) -> bool {
     match device.name() {
         Ok(device_name) => device_name.to_lowercase() ==
             requested_name.to_lowercase(),
@@ -49,56 +133,240 @@ pub fn match_device_name(requested_name: &String, device: &cpal::Device) -> bool
 pub fn find_device(
     requested_device: &Option<String>,
     mut device_iter: std::iter::Filter<cpal::Devices, for<'r> fn(&'r cpal::Device) -> bool>,
-    get_default: &dyn Fn() -> Option<cpal::Device>
-) -> Option<cpal::Device> {
+    get_default: &dyn Fn() -> Option<cpal::Device>,
+    strict: bool
+) -> Result<Option<cpal::Device>, EnvironmentError> {
     match requested_device {
         Some(requested_name) => {
             match device_iter.find(|device|
-                match_device_name(requested_name, &device)) {
-                Some(matched_device) => Some(matched_device),
-                None => get_default()
+                match_device_name(requested_name, device)) {
+                Some(matched_device) => Ok(Some(matched_device)),
+                None if strict => missing_device(requested_device, strict),
+                None => Ok(get_default())
             }
         },
-        None => get_default()
+        None => Ok(get_default())
+    }
+}
+
+// Determines what to do when the requested device isn't available.
+// In strict mode, this is an error; otherwise the device is omitted.
+pub fn missing_device(
+    requested_device: &Option<String>,
+    strict: bool
+) -> Result<Option<cpal::Device>, EnvironmentError> {
+    if strict {
+        Err(EnvironmentError::DeviceNotFound(requested_device.clone()))
+    } else {
+        Ok(None)
     }
 }
 
 pub fn find_output_device(
     requested_device: &Option<String>,
-    host: &cpal::Host
-) -> Option<cpal::Device> {
+    host: &cpal::Host,
+    strict: bool
+) -> Result<Option<cpal::Device>, EnvironmentError> {
     match host.output_devices() {
-        Ok(device_iter) => find_device(
-            requested_device, device_iter, &|| host.default_output_device()),
+        Ok(device_iter) => find_device(requested_device, device_iter,
+            &|| host.default_output_device(), strict),
 
         // We assume an error while accessing output devices
         // means that the requested device was not found.
-        Err(_e) => None
+        Err(_e) => missing_device(requested_device, strict)
     }
 }
 
 pub fn find_input_device(
     requested_device: &Option<String>,
-    host: &cpal::Host
-) -> Option<cpal::Device> {
+    host: &cpal::Host,
+    strict: bool
+) -> Result<Option<cpal::Device>, EnvironmentError> {
     match host.input_devices() {
-        Ok(device_iter) => find_device(
-            requested_device, device_iter, &|| host.default_input_device()),
+        Ok(device_iter) => find_device(requested_device, device_iter,
+            &|| host.default_input_device(), strict),
 
         // We assume an error while accessing input devices
         // means that the requested device was not found.
+        Err(_e) => missing_device(requested_device, strict)
+    }
+}
+
+// A stream configuration that was negotiated with a device,
+// along with the settings that were originally requested.
+#[derive(Debug, Clone)]
+pub struct NegotiatedConfig {
+    pub supported_config: cpal::SupportedStreamConfig,
+    pub buffer_size: cpal::BufferSize,
+    pub requested_sample_rate: Option<u32>,
+    pub requested_channels: Option<u32>,
+    pub requested_buffer_size: Option<u32>
+}
+
+impl NegotiatedConfig {
+    pub fn config(&self) -> cpal::StreamConfig {
+        let mut config = self.supported_config.config();
+        config.buffer_size = self.buffer_size.clone();
+        config
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.supported_config.sample_rate().0
+    }
+
+    pub fn channels(&self) -> u32 {
+        self.supported_config.channels() as u32
+    }
+
+    // Determines if the negotiated sample rate and channel count
+    // are the ones that were requested.
+    pub fn matches_request(&self) -> bool {
+        self.requested_sample_rate.iter()
+            .all(|&rate| rate == self.sample_rate()) &&
+        self.requested_channels.iter()
+            .all(|&channels| channels == self.channels())
+    }
+
+    pub fn fixed_buffer_size(&self) -> Option<u32> {
+        match self.buffer_size {
+            cpal::BufferSize::Fixed(frames) => Some(frames),
+            cpal::BufferSize::Default => None
+        }
+    }
+}
 
+fn fmt_requested(value: Option<u32>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "none".to_string()
+    }
+}
+
+impl fmt::Display for NegotiatedConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let buffer_size = match self.fixed_buffer_size() {
+            Some(frames) => frames.to_string(),
+            None => "default".to_string()
+        };
+
+        write!(f, "{} Hz (requested {}), {} channels (requested {}), \
+            {} frame buffer (requested {}), {:?} samples",
+            self.sample_rate(), fmt_requested(self.requested_sample_rate),
+            self.channels(), fmt_requested(self.requested_channels),
+            buffer_size, fmt_requested(self.requested_buffer_size),
+            self.supported_config.sample_format())
+    }
+}
+
+// Chooses the sample rate within the supported range
+// that is closest to the requested one.
+pub fn choose_sample_rate(requested: Option<u32>, min: u32, max: u32) -> u32 {
+    match requested {
+        Some(rate) => rate.clamp(min, max),
+        None => max
+    }
+}
+
+pub fn choose_buffer_size(
+    requested: Option<u32>,
+    supported: &cpal::SupportedBufferSize
+) -> cpal::BufferSize {
+    match (requested, supported) {
+        (Some(frames), cpal::SupportedBufferSize::Range {min, max}) =>
+            cpal::BufferSize::Fixed(frames.clamp(*min, *max)),
+
+        // If we don't know which sizes the device supports,
+        // asking for a particular one could cause the stream to fail.
+        _ => cpal::BufferSize::Default
+    }
+}
+
+// Ranks a candidate configuration; lower ranks are better.
+// Matching the requested sample rate is most important,
+// followed by the channel count (preferring more channels over fewer),
+// and then the sample format (preferring f32).
+pub fn rank_config(
+    requested_sample_rate: Option<u32>,
+    requested_channels: Option<u32>,
+    sample_rate: u32,
+    channels: u32,
+    sample_format: SampleFormat
+) -> (u32, u32, bool, u8) {
+    let sample_rate_distance = match requested_sample_rate {
+        Some(rate) => (rate as i64 - sample_rate as i64).unsigned_abs() as u32,
+        None => 0
+    };
+
+    let (channel_distance, too_few_channels) = match requested_channels {
+        Some(requested) => (
+            (requested as i64 - channels as i64).unsigned_abs() as u32,
+            channels < requested
+        ),
+        None => (0, false)
+    };
+
+    let format_rank = match sample_format {
+        SampleFormat::F32 => 0,
+        SampleFormat::I16 => 1,
+        SampleFormat::U16 => 2
+    };
+
+    (sample_rate_distance, channel_distance, too_few_channels, format_rank)
+}
+
+// Picks the configuration that best matches the requested settings.
+pub fn find_stream_config<I>(
+    configs: I,
+    requested_channels: Option<u32>,
+    settings: &EnvironmentSettings
+) -> Option<NegotiatedConfig>
+    where I: Iterator<Item = cpal::SupportedStreamConfigRange> {
+    let candidates = configs.map(|range| {
+        let sample_rate = choose_sample_rate(settings.sample_rate,
+            range.min_sample_rate().0, range.max_sample_rate().0);
+        range.with_sample_rate(cpal::SampleRate(sample_rate))
+    });
+
+    let best = candidates.min_by_key(|config| rank_config(
+        settings.sample_rate, requested_channels, config.sample_rate().0,
+        config.channels() as u32, config.sample_format()))?;
+
+    Some(NegotiatedConfig {
+        buffer_size: choose_buffer_size(settings.buffer_size,
+            best.buffer_size()),
+        supported_config: best,
+        requested_sample_rate: settings.sample_rate,
+        requested_channels,
+        requested_buffer_size: settings.buffer_size
+    })
+}
+
+pub fn find_output_stream_config(
+    device: &cpal::Device,
+    settings: &EnvironmentSettings
+) -> Option<NegotiatedConfig> {
+    match device.supported_output_configs() {
+        Ok(configs) => find_stream_config(
+            configs, settings.num_output_channels, settings),
+
+        // We assume that a device whose configurations
+        // can't be accessed can't be used for output.
         Err(_e) => None
     }
 }
 
-// TODO: Implement.
-pub fn find_stream_config(
+pub fn find_input_stream_config(
     device: &cpal::Device,
     settings: &EnvironmentSettings
-) -> Option<cpal::SupportedStreamConfig> {
-    let configs_range = device.supported_output_configs();
-    None
+) -> Option<NegotiatedConfig> {
+    match device.supported_input_configs() {
+        Ok(configs) => find_stream_config(
+            configs, settings.num_input_channels, settings),
+
+        // We assume that a device whose configurations
+        // can't be accessed can't be used for input.
+        Err(_e) => None
+    }
 }
 
 pub struct HostAudio {
@@ -110,36 +378,234 @@ impl HostAudio {
     pub fn new(
         settings: &EnvironmentSettings,
         host: &Host
-    ) -> HostAudio {
-        return HostAudio {
-            output: find_output_device(&settings.output_device, host),
-            input: find_input_device(&settings.input_device, host)
+    ) -> Result<HostAudio, EnvironmentError> {
+        let strict = is_strict(settings);
+
+        Ok(HostAudio {
+            output: find_output_device(
+                &settings.output_device, host, strict)?,
+            input: find_input_device(
+                &settings.input_device, host, strict)?
+        })
+    }
+}
+
+// Builds the AudioSettings that signals running in
+// a stream with the specified configuration should use.
+pub fn audio_settings(
+    config: &cpal::StreamConfig,
+    settings: &EnvironmentSettings
+) -> AudioSettings {
+    EnvironmentSettings {
+        sample_rate: Some(config.sample_rate.0),
+        num_output_channels: Some(config.channels as u32),
+        ..settings.clone()
+    }.audio_settings()
+}
+
+// Reads a graph's output one block at a time, so that it can fill
+// device buffers of any length. Frames that don't fit in one buffer
+// are carried over to the next, so the block size needn't divide
+// the buffer size.
+pub struct BlockReader<G> {
+    graph: G,
+    block_size: usize,
+    // The next frame to read from the current block.
+    position: usize
+}
+
+impl<G: Graph> BlockReader<G> {
+    pub fn new(graph: G, block_size: usize) -> BlockReader<G> {
+        BlockReader {
+            graph,
+            block_size,
+            // The first block hasn't been evaluated yet.
+            position: block_size
+        }
+    }
+
+    // Fills the interleaved device buffer with the graph's output,
+    // evaluating new blocks as they're needed.
+    pub fn write<T: Sample>(&mut self, data: &mut [T], num_channels: usize) {
+        let num_frames = data.len() / num_channels;
+        let mut offset = 0;
+
+        while offset < num_frames {
+            if self.position == self.block_size {
+                self.graph.evaluate();
+                self.position = 0;
+            }
+
+            let count = (self.block_size - self.position)
+                .min(num_frames - offset);
+            for channel in 0..num_channels {
+                let block = self.graph.output(channel);
+                for i in 0..count {
+                    let value = block.map_or(0.0, |buffer|
+                        buffer[self.position + i]);
+                    data[(offset + i) * num_channels + channel] =
+                        T::from(&value);
+                }
+            }
+
+            offset += count;
+            self.position += count;
         }
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct AudioConnectionError;
+fn build_output_stream<T, G, H>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    block_size: usize,
+    graph: G,
+    on_error: H
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+    where T: Sample, G: Graph + 'static,
+        H: FnMut(cpal::StreamError) + Send + 'static {
+    let num_channels = config.channels as usize;
+    let mut reader = BlockReader::new(graph, block_size);
+
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            reader.write(data, num_channels)
+        },
+        on_error
+    )
+}
+
+fn build_input_stream<T, F, H>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut on_frame: F,
+    on_error: H
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+    where T: Sample, F: FnMut(&[f32]) + Send + 'static,
+        H: FnMut(cpal::StreamError) + Send + 'static {
+    let device_channels = config.channels as usize;
+    let num_channels = device_channels.min(MAX_CHANNEL_COUNT);
+
+    device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            let mut frame = [0.0; MAX_CHANNEL_COUNT];
+            for device_frame in data.chunks(device_channels) {
+                for (sample, device_sample) in frame.iter_mut()
+                    .zip(device_frame.iter()) {
+                    *sample = device_sample.to_f32();
+                }
+
+                on_frame(&frame[0..num_channels]);
+            }
+        },
+        on_error
+    )
+}
+
+// The buffer size that devices are assumed to have
+// when they don't report a fixed one.
+pub const DEFAULT_BUFFER_SIZE: u32 = 1024;
+
+// A Graph that can read frames from the input device.
+pub trait InputGraph: Graph {
+    fn audio_input(&self) -> Option<Arc<AudioInput>>;
+}
+
+impl InputGraph for Composition {
+    fn audio_input(&self) -> Option<Arc<AudioInput>> {
+        Some(self.input().clone())
+    }
+}
+
+// The number of frames to buffer between the input and output devices,
+// so that a whole buffer of input has arrived by the time the graph
+// needs it, whichever device's callback runs first.
+pub fn input_latency(
+    input_buffer_size: Option<u32>,
+    output_buffer_size: Option<u32>
+) -> usize {
+    (input_buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE) +
+        output_buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE)) as usize
+}
 
 pub struct AudioConnection {
-    pub config: cpal::SupportedStreamConfig,
+    pub config: NegotiatedConfig,
     pub stream: cpal::Stream
 }
 
 impl AudioConnection {
-    // TODO: Implement.
-    pub fn new(
-        host_audio: &HostAudio,
-        settings: &EnvironmentSettings
-    ) -> Result<AudioConnection, AudioConnectionError> {
-        Err(AudioConnectionError)
+    // Opens an output stream on the device that plays the graph.
+    // Errors that occur while the stream is playing are reported
+    // to on_error.
+    pub fn new_output<G, H>(
+        device: &cpal::Device,
+        config: NegotiatedConfig,
+        block_size: usize,
+        graph: G,
+        on_error: H
+    ) -> Result<AudioConnection, EnvironmentError>
+        where G: Graph + 'static,
+            H: FnMut(cpal::StreamError) + Send + 'static {
+        let stream_config = config.config();
+
+        let stream = match config.supported_config.sample_format() {
+            SampleFormat::F32 => build_output_stream::<f32, G, H>(
+                device, &stream_config, block_size, graph, on_error),
+            SampleFormat::I16 => build_output_stream::<i16, G, H>(
+                device, &stream_config, block_size, graph, on_error),
+            SampleFormat::U16 => build_output_stream::<u16, G, H>(
+                device, &stream_config, block_size, graph, on_error)
+        }?;
+
+        stream.play()?;
+
+        Ok(AudioConnection {
+            config,
+            stream
+        })
+    }
+
+    // Opens an input stream on the device, which calls on_frame
+    // with each frame of samples that are received from the device,
+    // and on_error with errors that occur while the stream is open.
+    pub fn new_input<F, H>(
+        device: &cpal::Device,
+        config: NegotiatedConfig,
+        on_frame: F,
+        on_error: H
+    ) -> Result<AudioConnection, EnvironmentError>
+        where F: FnMut(&[f32]) + Send + 'static,
+            H: FnMut(cpal::StreamError) + Send + 'static {
+        let stream_config = config.config();
+
+        let stream = match config.supported_config.sample_format() {
+            SampleFormat::F32 => build_input_stream::<f32, F, H>(
+                device, &stream_config, on_frame, on_error),
+            SampleFormat::I16 => build_input_stream::<i16, F, H>(
+                device, &stream_config, on_frame, on_error),
+            SampleFormat::U16 => build_input_stream::<u16, F, H>(
+                device, &stream_config, on_frame, on_error)
+        }?;
+
+        stream.play()?;
+
+        Ok(AudioConnection {
+            config,
+            stream
+        })
     }
 }
 
 pub struct AudioConnections {
-    settings: EnvironmentSettings,
+    // The settings of the streams that were actually opened,
+    // which may differ from the ones that were requested.
+    pub settings: EnvironmentSettings,
     pub output: Option<AudioConnection>,
-    pub input: Option<AudioConnection>
+    pub input: Option<AudioConnection>,
+    // Why the input device couldn't be opened, outside strict mode,
+    // in which case the settings have no input device.
+    pub input_error: Option<EnvironmentError>
 }
 
 pub struct Environment {
@@ -149,7 +615,9 @@ pub struct Environment {
 }
 
 impl Environment {
-    pub fn new(options: EnvironmentSettings) -> Environment {
+    pub fn new(
+        options: EnvironmentSettings
+    ) -> Result<Environment, EnvironmentError> {
         // TODO: Find a better way to store defaults.
         let defaults = EnvironmentSettings {
             host: None,
@@ -158,56 +626,332 @@ impl Environment {
             num_input_channels: Some(2),
             num_output_channels: Some(2),
             sample_rate: Some(44100),
+            input_sample_rate: None,
             buffer_size: Some(128),
-            block_size: Some(64)
+            block_size: Some(64),
+            strict: Some(false)
         };
 
         let settings = flocking::utils::merge_options(
             &defaults, Some(&options));
-        let host = find_host(&settings);
+        let host = find_host(&settings)?;
         let host_audio = HostAudio::new(
-            &settings, &host);
+            &settings, &host)?;
 
-        Environment {
-            settings: settings,
-            host: host,
-            host_audio: host_audio
+        Ok(Environment {
+            settings,
+            host,
+            host_audio
+        })
+    }
+
+    // Finds the stream configuration that best matches the settings,
+    // failing in strict mode if it isn't an exact match.
+    fn negotiate(
+        &self,
+        device: &cpal::Device,
+        config: Option<NegotiatedConfig>
+    ) -> Result<NegotiatedConfig, EnvironmentError> {
+        match config {
+            Some(config) if config.matches_request() ||
+                !is_strict(&self.settings) => Ok(config),
+            _ => Err(EnvironmentError::NoMatchingConfig(
+                device_display_name(device)))
         }
     }
 
-    // TODO: Implement.
-    pub fn connect() -> AudioConnections {
-        // This is also where we'll have to examine
-        // the connections and produce an updated EnvironmentSettings
-        // object that represents the actual state of the
-        // audio connections. For example, we may end up with a
-        // stream config that has a different sample rate or number of
-        // channels than was specified.
-        AudioConnections {
-            settings: EnvironmentSettings {
-                // TODO: Fill this with the host's "display name."
-                host: None,
-
-                // TODO: Fill these with the input/output devices'
-                // display names.
-                input_device: None,
-                output_device: None,
-
-                // TODO: These can be sourced from
-                // the AudioConnection's config object.
-                // But what if the input and output values don't
-                // match?
-                // e.g. we connect to 4-channel EIE at 44100 for
-                // output, but we connect to the built-in microphone
-                // (2 channel 48000) for input.
-                num_input_channels: None,
-                num_output_channels: None,
-                sample_rate: None,
-                buffer_size: None,
-                block_size: None
+    // Opens an input stream that writes frames to the AudioInput.
+    fn connect_input<H>(
+        &self,
+        device: &cpal::Device,
+        audio_input: Arc<AudioInput>,
+        output_buffer_size: Option<u32>,
+        on_error: H
+    ) -> Result<AudioConnection, EnvironmentError>
+        where H: FnMut(cpal::StreamError) + Send + 'static {
+        let config = self.negotiate(device,
+            find_input_stream_config(device, &self.settings))?;
+
+        // TODO: Resample input from devices whose sample rate
+        // differs from the output's.
+        audio_input.set_latency(input_latency(
+            config.fixed_buffer_size(), output_buffer_size));
+        AudioConnection::new_input(device, config, move |frame| unsafe {
+            // The input stream is the only writer.
+            audio_input.write(frame)
+        }, on_error)
+    }
+
+    // Opens streams on the input and output devices and
+    // starts playing the graph produced by build_graph, which is called
+    // with the AudioSettings of the output stream that was actually opened.
+    // Frames from the input device are written to the graph's AudioInput.
+    // Outside strict mode, the output plays even if the input can't be
+    // opened; the reason is returned alongside the connections.
+    // Errors that occur while the streams are open are reported
+    // to on_stream_error.
+    pub fn connect<G, E, F, H>(
+        &self,
+        build_graph: F,
+        on_stream_error: H
+    ) -> Result<AudioConnections, EnvironmentError>
+        where G: InputGraph + 'static,
+            E: Into<Box<dyn Error + Send + Sync>>,
+            F: FnOnce(AudioSettings) -> Result<G, E>,
+            H: FnMut(cpal::StreamError) + Send + Clone + 'static {
+        let mut block_size = None;
+        let mut audio_input = None;
+        let output = match &self.host_audio.output {
+            Some(device) => {
+                let config = self.negotiate(device,
+                    find_output_stream_config(device, &self.settings))?;
+                let settings = audio_settings(
+                    &config.config(), &self.settings);
+                block_size = Some(settings.block_size as u32);
+                let graph = build_graph(settings)
+                    .map_err(|e| EnvironmentError::Graph(e.into()))?;
+                audio_input = graph.audio_input();
+
+                Some(AudioConnection::new_output(device, config,
+                    settings.block_size, graph, on_stream_error.clone())?)
             },
-            output: None,
-            input: None
+            None => None
+        };
+
+        // Without an output stream or a graph that reads the input,
+        // there's no need to open it.
+        let mut input_error = None;
+        let input = match (&self.host_audio.input, audio_input) {
+            _ if self.settings.num_input_channels == Some(0) => None,
+            (Some(device), Some(audio_input)) => {
+                let output_buffer_size = output.as_ref()
+                    .and_then(|c| c.config.fixed_buffer_size());
+                match self.connect_input(device, audio_input,
+                    output_buffer_size, on_stream_error) {
+                    Ok(input) => Some(input),
+                    Err(e) if !is_strict(&self.settings) => {
+                        input_error = Some(e);
+                        None
+                    },
+                    Err(e) => return Err(e)
+                }
+            },
+            _ => None
+        };
+
+        let settings = self.resolve_settings(&output, &input, block_size);
+
+        Ok(AudioConnections {
+            settings,
+            output,
+            input,
+            input_error
+        })
+    }
+
+    // Produces an EnvironmentSettings object that represents the actual
+    // state of the audio connections. For example, we may end up with a
+    // stream config that has a different sample rate or number of
+    // channels than was specified, or we may connect to a 4-channel
+    // output device at 44100 Hz while connecting to a 2-channel
+    // input device at 48000 Hz.
+    fn resolve_settings(
+        &self,
+        output: &Option<AudioConnection>,
+        input: &Option<AudioConnection>,
+        block_size: Option<u32>
+    ) -> EnvironmentSettings {
+        let output_config = output.as_ref().map(|c| &c.config);
+        let input_config = input.as_ref().map(|c| &c.config);
+
+        EnvironmentSettings {
+            host: Some(self.host.id().name().to_string()),
+            input_device: input.as_ref().and(self.host_audio.input.as_ref())
+                .map(device_display_name),
+            output_device: output.as_ref().and(self.host_audio.output.as_ref())
+                .map(device_display_name),
+            num_input_channels: input_config.map(|c| c.channels()),
+            num_output_channels: output_config.map(|c| c.channels()),
+            sample_rate: output_config.or(input_config)
+                .map(|c| c.sample_rate()),
+            input_sample_rate: input_config.map(|c| c.sample_rate()),
+            buffer_size: output_config.or(input_config)
+                .and_then(|c| c.fixed_buffer_size()),
+            block_size,
+            strict: self.settings.strict
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libflock::evaluator::Evaluator;
+    use libflock::signals::{AudioOut, Connection, MAX_BLOCK_SIZE};
+
+    #[test]
+    fn missing_devices_are_errors_when_strict() {
+        let requested = Some("Eurorack Interface".to_string());
+
+        assert!(matches!(missing_device(&requested, false), Ok(None)),
+            "Missing devices are omitted by default");
+
+        match missing_device(&requested, true) {
+            Err(EnvironmentError::DeviceNotFound(name)) =>
+                assert_eq!(requested, name),
+            _ => panic!("A missing device should be an error when strict")
+        }
+    }
+
+    #[test]
+    fn sample_rate_is_clamped_to_range() {
+        assert_eq!(48000, choose_sample_rate(Some(48000), 44100, 96000),
+            "The requested rate is used when it's supported");
+        assert_eq!(44100, choose_sample_rate(Some(22050), 44100, 96000));
+        assert_eq!(96000, choose_sample_rate(Some(192000), 44100, 96000));
+        assert_eq!(96000, choose_sample_rate(None, 44100, 96000),
+            "The highest rate is used when none was requested");
+    }
+
+    #[test]
+    fn buffer_size_is_clamped_to_range() {
+        let range = cpal::SupportedBufferSize::Range {
+            min: 64,
+            max: 4096
+        };
+
+        assert_eq!(cpal::BufferSize::Fixed(128),
+            choose_buffer_size(Some(128), &range));
+        assert_eq!(cpal::BufferSize::Fixed(64),
+            choose_buffer_size(Some(16), &range));
+        assert_eq!(cpal::BufferSize::Fixed(4096),
+            choose_buffer_size(Some(8192), &range));
+        assert_eq!(cpal::BufferSize::Default,
+            choose_buffer_size(Some(128),
+                &cpal::SupportedBufferSize::Unknown));
+        assert_eq!(cpal::BufferSize::Default,
+            choose_buffer_size(None, &range));
+    }
+
+    #[test]
+    fn exact_sample_rate_is_ranked_first() {
+        let exact_rate = rank_config(Some(48000), Some(2),
+            48000, 8, SampleFormat::I16);
+        let exact_channels = rank_config(Some(48000), Some(2),
+            44100, 2, SampleFormat::F32);
+        assert!(exact_rate < exact_channels);
+    }
+
+    #[test]
+    fn nearest_channel_count_is_ranked_next() {
+        let two = rank_config(Some(44100), Some(2),
+            44100, 2, SampleFormat::I16);
+        let four = rank_config(Some(44100), Some(2),
+            44100, 4, SampleFormat::F32);
+        let three = rank_config(Some(44100), Some(4),
+            44100, 3, SampleFormat::F32);
+        let five = rank_config(Some(44100), Some(4),
+            44100, 5, SampleFormat::F32);
+
+        assert!(two < four);
+        assert!(five < three,
+            "More channels are preferred over fewer at the same distance");
+    }
+
+    #[test]
+    fn input_latency_covers_both_buffers() {
+        assert_eq!(768, input_latency(Some(256), Some(512)));
+        assert_eq!(256 + DEFAULT_BUFFER_SIZE as usize,
+            input_latency(Some(256), None),
+            "Buffers of unknown size are assumed to be the default size");
+    }
+
+    // Outputs the index of each frame it has generated.
+    struct Counter {
+        block: [f32; MAX_BLOCK_SIZE],
+        block_size: usize,
+        num_frames: usize,
+        num_blocks: usize
+    }
+
+    impl Graph for Counter {
+        fn evaluate(&mut self) {
+            for sample in self.block.iter_mut().take(self.block_size) {
+                *sample = self.num_frames as f32;
+                self.num_frames += 1;
+            }
+            self.num_blocks += 1;
+        }
+
+        fn output(&self, channel: usize) -> Option<&[f32; MAX_BLOCK_SIZE]> {
+            match channel {
+                0 => Some(&self.block),
+                _ => None
+            }
         }
     }
+
+    #[test]
+    fn blocks_are_carried_over_between_buffers() {
+        let mut reader = BlockReader::new(Counter {
+            block: [0.0; MAX_BLOCK_SIZE],
+            block_size: 4,
+            num_frames: 0,
+            num_blocks: 0
+        }, 4);
+
+        let mut frames = Vec::new();
+        for buffer_size in [3, 6, 1, 5] {
+            let mut buffer = vec![0.0_f32; buffer_size * 2];
+            reader.write(&mut buffer, 2);
+            frames.extend(buffer.chunks(2).map(|frame| frame[0]));
+            assert!(buffer.chunks(2).all(|frame| frame[1] == 0.0));
+        }
+
+        let expected: Vec<f32> = (0..15).map(|i| i as f32).collect();
+        assert_eq!(expected, frames);
+        assert_eq!(4, reader.graph.num_blocks,
+            "Only as many blocks as were needed were evaluated");
+    }
+
+    #[test]
+    fn output_is_interleaved_in_the_device_format() {
+        let mut out = AudioOut::new(AudioSettings {
+            sample_rate: 44100.0,
+            block_size: 2,
+            num_channels: 2
+        });
+        out.inputs.sources[1] = Connection::new_constant(0.5);
+        let mut graph = Evaluator::new();
+        let id = graph.add(&mut out).unwrap();
+        graph.set_output(id).unwrap();
+        let mut reader = BlockReader::new(graph, 2);
+
+        // The device has a third channel that the graph doesn't output.
+        let mut floats = [1.0_f32; 12];
+        reader.write(&mut floats, 3);
+        assert_eq!([0.0, 0.5, 0.0, 0.0, 0.5, 0.0], floats[0..6]);
+        assert_eq!(floats[0..6], floats[6..12]);
+
+        let mut signed = [1_i16; 6];
+        reader.write(&mut signed, 3);
+        assert_eq!([0, 0.5_f32.to_i16(), 0], signed[0..3]);
+
+        let mut unsigned = [1_u16; 6];
+        reader.write(&mut unsigned, 3);
+        assert_eq!([32768, 0.5_f32.to_u16(), 32768], unsigned[0..3]);
+    }
+
+    #[test]
+    fn f32_is_preferred() {
+        let f32_rank = rank_config(Some(44100), Some(2),
+            44100, 2, SampleFormat::F32);
+        let i16_rank = rank_config(Some(44100), Some(2),
+            44100, 2, SampleFormat::I16);
+        let u16_rank = rank_config(Some(44100), Some(2),
+            44100, 2, SampleFormat::U16);
+
+        assert!(f32_rank < i16_rank);
+        assert!(i16_rank < u16_rank);
+    }
 }
@@ -1,2 +1,7 @@
 pub mod env;
+pub mod midi;
+pub mod osc;
+pub mod render;
+#[cfg(feature = "server")]
+pub mod server;
 pub mod utils;
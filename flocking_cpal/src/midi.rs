@@ -0,0 +1,427 @@
+use std::{io, thread};
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use flocking::{MidiControlSpec, MidiMessageType, MidiSpec};
+use flocking::nexus::{Change, Nexus, NexusError};
+
+// Reads MIDI byte streams, such as raw MIDI devices or recordings,
+// and uses their notes, controllers and pitch bends to set the inputs
+// and parameters of a running composition, as mapped by the controls
+// in the composition's midi section. Like OSC messages, changes are
+// made through a Nexus so that they reach the realtime thread
+// without locking it.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MidiMessage {
+    NoteOff { channel: u8, note: u8, velocity: u8 },
+    NoteOn { channel: u8, note: u8, velocity: u8 },
+    ControlChange { channel: u8, control: u8, value: u8 },
+    // A 14-bit value, centred on 8192.
+    PitchBend { channel: u8, value: u16 }
+}
+
+// Assembles messages from a stream of bytes, one byte at a time.
+// Running status is supported; system messages, such as SysEx
+// and clock messages, are skipped.
+#[derive(Default, Debug)]
+pub struct MidiParser {
+    status: Option<u8>,
+    data: [u8; 2],
+    num_data: usize
+}
+
+impl MidiParser {
+    pub fn parse(&mut self, byte: u8) -> Option<MidiMessage> {
+        match byte {
+            // Realtime messages may appear between the bytes
+            // of any other message.
+            0xF8..=0xFF => None,
+            // Other system messages cancel running status.
+            0xF0..=0xF7 => {
+                self.status = None;
+                None
+            },
+            0x80..=0xEF => {
+                self.status = Some(byte);
+                self.num_data = 0;
+                None
+            },
+            _ => {
+                let status = self.status?;
+                self.data[self.num_data] = byte;
+                self.num_data += 1;
+
+                if self.num_data < data_length(status) {
+                    return None
+                }
+
+                // Keep the status for subsequent messages.
+                self.num_data = 0;
+                message(status, self.data)
+            }
+        }
+    }
+}
+
+fn data_length(status: u8) -> usize {
+    match status & 0xF0 {
+        // Program changes and channel pressure.
+        0xC0 | 0xD0 => 1,
+        _ => 2
+    }
+}
+
+fn message(status: u8, data: [u8; 2]) -> Option<MidiMessage> {
+    let channel = status & 0x0F;
+    let [first, second] = data;
+
+    match status & 0xF0 {
+        0x80 => Some(MidiMessage::NoteOff {
+            channel,
+            note: first,
+            velocity: second
+        }),
+        // Notes with a velocity of 0 are released.
+        0x90 if second == 0 => Some(MidiMessage::NoteOff {
+            channel,
+            note: first,
+            velocity: 0
+        }),
+        0x90 => Some(MidiMessage::NoteOn {
+            channel,
+            note: first,
+            velocity: second
+        }),
+        0xB0 => Some(MidiMessage::ControlChange {
+            channel,
+            control: first,
+            value: second
+        }),
+        0xE0 => Some(MidiMessage::PitchBend {
+            channel,
+            value: (second as u16) << 7 | first as u16
+        }),
+        // Aftertouch and program changes aren't mapped.
+        _ => None
+    }
+}
+
+pub fn note_to_frequency(note: u8) -> f32 {
+    440.0 * 2.0_f32.powf((note as f32 - 69.0) / 12.0)
+}
+
+// Converts parsed messages into changes to the mapped signals.
+// Notes are monophonic: when a note is released, the frequency
+// returns to the most recent note that's still held.
+pub struct MidiInput {
+    parser: MidiParser,
+    controls: Vec<MidiControlSpec>,
+    // The channels and numbers of held notes, most recent last.
+    held_notes: Vec<(u8, u8)>
+}
+
+impl MidiInput {
+    pub fn new(spec: &MidiSpec) -> MidiInput {
+        MidiInput {
+            parser: MidiParser::default(),
+            controls: spec.controls.iter().flatten()
+                .map(|(_, control)| control.clone())
+                .collect(),
+            held_notes: Vec::new()
+        }
+    }
+
+    // Parses the bytes and applies the changes of each complete message.
+    // Changes only set values, so they never alter the graph's topology;
+    // a change that's rejected, e.g. because its input is connected
+    // to another signal, doesn't stop the others from being applied.
+    pub fn receive(&mut self, bytes: &[u8], nexus: &mut Nexus) ->
+        Result<(), NexusError> {
+        let mut result = Ok(());
+        for byte in bytes {
+            if let Some(message) = self.parser.parse(*byte) {
+                for change in self.changes(&message) {
+                    if let Err(e) = nexus.apply(change) {
+                        result = result.and(Err(e));
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    pub fn changes(&mut self, message: &MidiMessage) -> Vec<Change> {
+        match *message {
+            MidiMessage::NoteOn { channel, note, .. } => {
+                self.held_notes.retain(|held| *held != (channel, note));
+                self.held_notes.push((channel, note));
+            },
+            MidiMessage::NoteOff { channel, note, .. } =>
+                self.held_notes.retain(|held| *held != (channel, note)),
+            _ => ()
+        }
+
+        let held_notes = &self.held_notes;
+        self.controls.iter().filter_map(|control| {
+            let value = control_value(control, message, held_notes)?;
+            control.control.change(value)
+        }).collect()
+    }
+}
+
+fn control_value(
+    control: &MidiControlSpec,
+    message: &MidiMessage,
+    held_notes: &[(u8, u8)]
+) -> Option<f32> {
+    let listens_to = |channel: u8| control.channel
+        .is_none_or(|listening| listening == channel);
+    let last_held = held_notes.iter().rev()
+        .find(|(channel, _)| listens_to(*channel))
+        .map(|(_, note)| *note);
+
+    match (control.message, *message) {
+        (MidiMessageType::Frequency, MidiMessage::NoteOn { channel, .. } |
+            MidiMessage::NoteOff { channel, .. }) if listens_to(channel) =>
+            last_held.map(note_to_frequency),
+        (MidiMessageType::Velocity, MidiMessage::NoteOn {
+            channel, velocity, ..
+        }) if listens_to(channel) => Some(velocity as f32 / 127.0),
+        (MidiMessageType::Gate, MidiMessage::NoteOn { channel, .. } |
+            MidiMessage::NoteOff { channel, .. }) if listens_to(channel) =>
+            Some(if last_held.is_some() { 1.0 } else { 0.0 }),
+        (MidiMessageType::Control, MidiMessage::ControlChange {
+            channel, control: number, value
+        }) if listens_to(channel) && control.number == Some(number) =>
+            Some(value as f32 / 127.0),
+        (MidiMessageType::PitchBend, MidiMessage::PitchBend {
+            channel, value
+        }) if listens_to(channel) => Some((value as f32 - 8192.0) / 8192.0),
+        _ => None
+    }
+}
+
+// Reads MIDI from a device on its own thread
+// until the device is closed or fails.
+pub struct MidiListener {
+    thread: thread::JoinHandle<io::Result<()>>
+}
+
+impl MidiListener {
+    // Messages that can't be applied are reported to on_error,
+    // which is called on the listener's thread.
+    pub fn start<R, F>(
+        nexus: Arc<Mutex<Nexus>>,
+        mut device: R,
+        spec: &MidiSpec,
+        mut on_error: F
+    ) -> MidiListener
+        where R: Read + Send + 'static, F: FnMut(NexusError) + Send + 'static {
+        let mut input = MidiInput::new(spec);
+
+        let thread = thread::spawn(move || {
+            let mut buffer = [0; 256];
+            loop {
+                let length = match device.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(length) => length,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted =>
+                        continue,
+                    Err(e) => return Err(e)
+                };
+
+                let mut nexus = nexus.lock().unwrap();
+                if let Err(e) = input.receive(&buffer[..length], &mut nexus) {
+                    on_error(e);
+                }
+            }
+
+            Ok(())
+        });
+
+        MidiListener { thread }
+    }
+
+    // Waits for the device to be closed,
+    // returning the error that stopped the listener if reading it failed.
+    pub fn join(self) -> io::Result<()> {
+        self.thread.join().unwrap_or(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use flocking::{CompositionSpec, ConnectionSpec, ControlSpec,
+        EnvironmentSettings, SignalSpec};
+    use flocking::builder::Composition;
+    use libflock::evaluator::Graph;
+
+    fn midi_control(
+        message: MidiMessageType,
+        signal: &str,
+        input: Option<&str>,
+        parameter: Option<&str>
+    ) -> MidiControlSpec {
+        MidiControlSpec {
+            message,
+            number: None,
+            channel: None,
+            control: ControlSpec {
+                signal: signal.to_string(),
+                input: input.map(str::to_string),
+                parameter: parameter.map(str::to_string),
+                mul: None,
+                add: None
+            }
+        }
+    }
+
+    fn synth_midi() -> MidiSpec {
+        let mut cutoff = midi_control(MidiMessageType::Control,
+            "carrier", Some("mul"), None);
+        cutoff.number = Some(74);
+
+        MidiSpec {
+            device: None,
+            controls: Some([
+                ("pitch".to_string(), midi_control(MidiMessageType::Frequency,
+                    "carrier", Some("freq"), None)),
+                ("gate".to_string(), midi_control(MidiMessageType::Gate,
+                    "gate", None, Some("value"))),
+                ("cutoff".to_string(), cutoff)
+            ].into())
+        }
+    }
+
+    fn start() -> (Nexus, Composition) {
+        let mut gate = SignalSpec::new("value");
+        gate.parameters = Some([("value".to_string(), 0.0)].into());
+
+        let spec = CompositionSpec {
+            environment: EnvironmentSettings {
+                block_size: Some(4),
+                num_output_channels: Some(1),
+                ..Default::default()
+            },
+            signals: Some([
+                ("gate".to_string(), gate),
+                ("carrier".to_string(), SignalSpec::new("sine"))
+            ].into()),
+            output: Some("gate".to_string()),
+            midi: Some(synth_midi()),
+            ..Default::default()
+        };
+        let settings = spec.environment.audio_settings();
+
+        Nexus::new(spec, settings).unwrap()
+    }
+
+    #[test]
+    fn running_status_and_realtime_bytes_are_parsed() {
+        let mut parser = MidiParser::default();
+        let bytes = [
+            0x91, 60, 100,
+            // A clock tick in the middle of a message.
+            64, 0xF8, 90,
+            // A SysEx message, which is skipped.
+            0xF0, 0x7E, 0x01, 0xF7,
+            0xE0, 0x00, 0x40,
+            0x80, 60, 0
+        ];
+
+        let messages: Vec<MidiMessage> = bytes.iter()
+            .filter_map(|byte| parser.parse(*byte)).collect();
+
+        assert_eq!(vec![
+            MidiMessage::NoteOn { channel: 1, note: 60, velocity: 100 },
+            MidiMessage::NoteOn { channel: 1, note: 64, velocity: 90 },
+            MidiMessage::PitchBend { channel: 0, value: 8192 },
+            MidiMessage::NoteOff { channel: 0, note: 60, velocity: 0 }
+        ], messages);
+    }
+
+    #[test]
+    fn notes_are_mapped_to_frequencies_and_gates() {
+        let mut input = MidiInput::new(&synth_midi());
+        let value_of = |changes: &[Change], signal: &str| changes.iter()
+            .find_map(|change| match change {
+                Change::SetInput { signal: s, value, .. } |
+                Change::SetParameter { signal: s, value, .. }
+                    if s == signal => Some(*value),
+                _ => None
+            });
+
+        let changes = input.changes(&MidiMessage::NoteOn {
+            channel: 0, note: 69, velocity: 127
+        });
+        assert_eq!(Some(440.0), value_of(&changes, "carrier"));
+        assert_eq!(Some(1.0), value_of(&changes, "gate"));
+
+        input.changes(&MidiMessage::NoteOn {
+            channel: 0, note: 81, velocity: 127
+        });
+        let changes = input.changes(&MidiMessage::NoteOff {
+            channel: 0, note: 81, velocity: 0
+        });
+        assert_eq!(Some(440.0), value_of(&changes, "carrier"));
+        assert_eq!(Some(1.0), value_of(&changes, "gate"));
+
+        let changes = input.changes(&MidiMessage::NoteOff {
+            channel: 0, note: 69, velocity: 0
+        });
+        assert_eq!(None, value_of(&changes, "carrier"));
+        assert_eq!(Some(0.0), value_of(&changes, "gate"));
+
+        let changes = input.changes(&MidiMessage::ControlChange {
+            channel: 3, control: 74, value: 127
+        });
+        assert_eq!(Some(1.0), value_of(&changes, "carrier"));
+        assert!(input.changes(&MidiMessage::ControlChange {
+            channel: 3, control: 1, value: 127
+        }).is_empty());
+    }
+
+    #[test]
+    fn recorded_midi_reaches_the_graph() {
+        let (nexus, mut composition) = start();
+        let nexus = Arc::new(Mutex::new(nexus));
+        let recording = Cursor::new(vec![0x90, 60, 100]);
+
+        let (errors, received) = std::sync::mpsc::channel();
+        let listener = MidiListener::start(nexus.clone(), recording,
+            &synth_midi(), move |e| {
+                let _ = errors.send(e);
+            });
+        assert!(listener.join().is_ok());
+        assert!(received.try_recv().is_err(), "Every change was applied");
+        composition.evaluate();
+
+        assert_eq!(1.0, composition.output(0).unwrap()[0]);
+        let nexus = nexus.lock().unwrap();
+        let freq = nexus.signal("carrier").unwrap()
+            .inputs.as_ref().unwrap()["freq"];
+        assert!((freq - 261.626).abs() < 0.001);
+    }
+
+    #[test]
+    fn connected_inputs_are_left_alone() {
+        let (mut nexus, mut composition) = start();
+        nexus.create_signal("lfo", SignalSpec::new("sine")).unwrap();
+        nexus.connect("vibrato",
+            ConnectionSpec::new("lfo", "carrier", "freq")).unwrap();
+        let mut input = MidiInput::new(&synth_midi());
+
+        assert_eq!(Err(NexusError::InputConnected(
+                "carrier".to_string(), "freq".to_string())),
+            input.receive(&[0x90, 60, 100], &mut nexus));
+        composition.evaluate();
+
+        assert!(nexus.poll().is_empty());
+        assert!(nexus.connection("vibrato").is_some());
+        assert_eq!(1.0, composition.output(0).unwrap()[0],
+            "The gate was still opened");
+    }
+}
@@ -0,0 +1,454 @@
+use std::{error::Error, fmt, io, thread};
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use flocking::{ControlSpec, OscSpec};
+use flocking::nexus::{Nexus, NexusError};
+
+// Receives OSC messages over UDP and uses them to set the inputs and
+// parameters of a running composition. Messages sent to
+// /signal/<id>/<name> set the named input or parameter of the signal,
+// and the addresses in the composition's osc section can be mapped
+// to any input or parameter. Changes are made through a Nexus,
+// so they reach the realtime thread without locking it.
+
+pub const DEFAULT_PORT: u16 = 57121;
+
+// How often the listener checks whether it should stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// The largest packet that can be sent over UDP.
+const MAX_PACKET_SIZE: usize = 65536;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum OscArgument {
+    Int(i32),
+    Float(f32),
+    Long(i64),
+    Double(f64),
+    String(String),
+    True,
+    False
+}
+
+impl OscArgument {
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            OscArgument::Int(value) => Some(*value as f32),
+            OscArgument::Float(value) => Some(*value),
+            OscArgument::Long(value) => Some(*value as f32),
+            OscArgument::Double(value) => Some(*value as f32),
+            OscArgument::True => Some(1.0),
+            OscArgument::False => Some(0.0),
+            OscArgument::String(_) => None
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct OscMessage {
+    pub address: String,
+    pub arguments: Vec<OscArgument>
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum OscError {
+    Truncated,
+    InvalidString,
+    MissingTypeTags,
+    UnsupportedType(char),
+    // No signal is mapped to the message's address.
+    UnmappedAddress(String),
+    // The message doesn't have a numeric argument.
+    MissingValue(String),
+    Nexus(NexusError)
+}
+
+impl fmt::Display for OscError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OscError::Truncated => write!(f, "The OSC packet is truncated."),
+            OscError::InvalidString =>
+                write!(f, "The OSC packet contains an invalid string."),
+            OscError::MissingTypeTags =>
+                write!(f, "The OSC message has no type tags."),
+            OscError::UnsupportedType(tag) =>
+                write!(f, "The OSC type {} isn't supported.", tag),
+            OscError::UnmappedAddress(address) =>
+                write!(f, "No signal is mapped to {}.", address),
+            OscError::MissingValue(address) =>
+                write!(f, "The message to {} has no numeric value.", address),
+            OscError::Nexus(e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl Error for OscError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OscError::Nexus(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<NexusError> for OscError {
+    fn from(e: NexusError) -> Self {
+        OscError::Nexus(e)
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], OscError> {
+        let end = self.offset.checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(OscError::Truncated)?;
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+
+        Ok(bytes)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], OscError> {
+        self.take(N).map(|bytes| bytes.try_into().expect("N bytes"))
+    }
+
+    // OSC strings are null-terminated and padded to a multiple of 4 bytes.
+    fn string(&mut self) -> Result<&'a str, OscError> {
+        let remaining = &self.bytes[self.offset..];
+        let length = remaining.iter().position(|byte| *byte == 0)
+            .ok_or(OscError::Truncated)?;
+        let string = std::str::from_utf8(&remaining[..length])
+            .map_err(|_| OscError::InvalidString)?;
+        self.take((length / 4 + 1) * 4)?;
+
+        Ok(string)
+    }
+
+    fn blob_length(&mut self) -> Result<usize, OscError> {
+        let length = i32::from_be_bytes(self.take_array()?);
+        usize::try_from(length).map_err(|_| OscError::Truncated)
+    }
+}
+
+fn parse_message(bytes: &[u8]) -> Result<OscMessage, OscError> {
+    let mut reader = Reader { bytes, offset: 0 };
+    let address = reader.string()?.to_string();
+
+    // Some older implementations omit the type tags of empty messages.
+    if reader.offset == bytes.len() {
+        return Ok(OscMessage { address, arguments: Vec::new() })
+    }
+
+    let type_tags = reader.string()?;
+    let type_tags = type_tags.strip_prefix(',')
+        .ok_or(OscError::MissingTypeTags)?;
+
+    let mut arguments = Vec::new();
+    for tag in type_tags.chars() {
+        let argument = match tag {
+            'i' => OscArgument::Int(i32::from_be_bytes(reader.take_array()?)),
+            'f' => OscArgument::Float(
+                f32::from_be_bytes(reader.take_array()?)),
+            'h' => OscArgument::Long(
+                i64::from_be_bytes(reader.take_array()?)),
+            'd' => OscArgument::Double(
+                f64::from_be_bytes(reader.take_array()?)),
+            's' => OscArgument::String(reader.string()?.to_string()),
+            'T' => OscArgument::True,
+            'F' => OscArgument::False,
+            // Arguments without any data.
+            'N' | 'I' => continue,
+            _ => return Err(OscError::UnsupportedType(tag))
+        };
+        arguments.push(argument);
+    }
+
+    Ok(OscMessage { address, arguments })
+}
+
+// Parses a packet containing either a message or a bundle.
+// The messages in bundles are returned in order;
+// their time tags are ignored and they take effect immediately.
+pub fn parse_packet(bytes: &[u8]) -> Result<Vec<OscMessage>, OscError> {
+    let mut messages = Vec::new();
+    parse_into(bytes, &mut messages)?;
+
+    Ok(messages)
+}
+
+fn parse_into(bytes: &[u8], messages: &mut Vec<OscMessage>) ->
+    Result<(), OscError> {
+    if !bytes.starts_with(b"#bundle\0") {
+        messages.push(parse_message(bytes)?);
+        return Ok(())
+    }
+
+    // Skip the bundle's name and time tag.
+    let mut reader = Reader { bytes, offset: 16 };
+    if bytes.len() < reader.offset {
+        return Err(OscError::Truncated)
+    }
+
+    while reader.offset < bytes.len() {
+        let length = reader.blob_length()?;
+        parse_into(reader.take(length)?, messages)?;
+    }
+
+    Ok(())
+}
+
+// Sets the input or parameter that the message's address refers to.
+pub fn dispatch(
+    message: &OscMessage,
+    addresses: &HashMap<String, ControlSpec>,
+    nexus: &mut Nexus
+) -> Result<(), OscError> {
+    let value = message.arguments.first()
+        .and_then(|argument| argument.as_f32())
+        .ok_or_else(|| OscError::MissingValue(message.address.clone()))?;
+
+    if let Some(change) = addresses.get(&message.address)
+        .and_then(|control| control.change(value)) {
+        return nexus.apply(change).map_err(OscError::from)
+    }
+
+    let segments: Vec<&str> = message.address.split('/').collect();
+    match segments.as_slice() {
+        ["", "signal", signal, name] =>
+            nexus.set_value(signal, name, value).map_err(OscError::from),
+        _ => Err(OscError::UnmappedAddress(message.address.clone()))
+    }
+}
+
+pub struct OscListener {
+    address: SocketAddr,
+    running: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<io::Result<()>>>
+}
+
+impl OscListener {
+    // Starts listening on its own thread. Messages that can't be
+    // applied are reported to on_error, which is called on that thread.
+    pub fn start<A, F>(
+        nexus: Arc<Mutex<Nexus>>,
+        address: A,
+        spec: &OscSpec,
+        mut on_error: F
+    ) -> io::Result<OscListener>
+        where A: ToSocketAddrs, F: FnMut(OscError) + Send + 'static {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        let address = socket.local_addr()?;
+
+        let addresses = spec.addresses.clone().unwrap_or_default();
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+
+        let thread = thread::spawn(move || {
+            let mut buffer = vec![0; MAX_PACKET_SIZE];
+            while thread_running.load(Ordering::Acquire) {
+                let length = match socket.recv(&mut buffer) {
+                    Ok(length) => length,
+                    Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock |
+                        io::ErrorKind::TimedOut) => continue,
+                    Err(e) => return Err(e)
+                };
+
+                let result = parse_packet(&buffer[..length])
+                    .and_then(|messages| {
+                        let mut nexus = nexus.lock().unwrap();
+                        messages.iter().try_for_each(|message|
+                            dispatch(message, &addresses, &mut nexus))
+                    });
+                if let Err(e) = result {
+                    on_error(e);
+                }
+            }
+
+            Ok(())
+        });
+
+        Ok(OscListener {
+            address,
+            running,
+            thread: Some(thread)
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    // Stops listening, returning the error that stopped the listener
+    // early if its socket failed.
+    pub fn stop(mut self) -> io::Result<()> {
+        self.stop_thread()
+    }
+
+    fn stop_thread(&mut self) -> io::Result<()> {
+        self.running.store(false, Ordering::Release);
+        match self.thread.take().map(|thread| thread.join()) {
+            Some(Ok(result)) => result,
+            _ => Ok(())
+        }
+    }
+}
+
+impl Drop for OscListener {
+    fn drop(&mut self) {
+        let _ = self.stop_thread();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flocking::{CompositionSpec, EnvironmentSettings, SignalSpec};
+    use flocking::builder::Composition;
+    use libflock::evaluator::Graph;
+
+    // Null-terminates and pads a string to a multiple of 4 bytes.
+    fn pad(bytes: &mut Vec<u8>) {
+        bytes.resize((bytes.len() / 4 + 1) * 4, 0);
+    }
+
+    fn encode(address: &str, value: f32) -> Vec<u8> {
+        let mut bytes = address.as_bytes().to_vec();
+        pad(&mut bytes);
+        bytes.extend_from_slice(b",f");
+        pad(&mut bytes);
+        bytes.extend_from_slice(&value.to_be_bytes());
+
+        bytes
+    }
+
+    fn start() -> (Nexus, Composition) {
+        let mut level = SignalSpec::new("value");
+        level.parameters = Some([("value".to_string(), 0.0)].into());
+
+        let spec = CompositionSpec {
+            environment: EnvironmentSettings {
+                block_size: Some(4),
+                num_output_channels: Some(1),
+                ..Default::default()
+            },
+            signals: Some([
+                ("level".to_string(), level),
+                ("osc".to_string(), SignalSpec::new("sine"))
+            ].into()),
+            output: Some("level".to_string()),
+            ..Default::default()
+        };
+        let settings = spec.environment.audio_settings();
+
+        Nexus::new(spec, settings).unwrap()
+    }
+
+    #[test]
+    fn messages_are_parsed() {
+        let mut bytes = b"/signal/osc/freq\0\0\0\0,ifsT\0\0\0".to_vec();
+        bytes.extend_from_slice(&440_i32.to_be_bytes());
+        bytes.extend_from_slice(&0.5_f32.to_be_bytes());
+        bytes.extend_from_slice(b"hi\0\0");
+
+        assert_eq!(Ok(vec![OscMessage {
+            address: "/signal/osc/freq".to_string(),
+            arguments: vec![
+                OscArgument::Int(440),
+                OscArgument::Float(0.5),
+                OscArgument::String("hi".to_string()),
+                OscArgument::True
+            ]
+        }]), parse_packet(&bytes));
+
+        assert_eq!(Err(OscError::Truncated), parse_packet(&bytes[..28]));
+        assert_eq!(Err(OscError::UnsupportedType('b')),
+            parse_packet(b"/a\0\0,b\0\0"));
+    }
+
+    #[test]
+    fn bundles_are_flattened() {
+        let first = encode("/a", 1.0);
+        let second = encode("/b", 2.0);
+
+        let mut bundle = b"#bundle\0".to_vec();
+        bundle.extend_from_slice(&1_u64.to_be_bytes());
+        for message in [&first, &second] {
+            bundle.extend_from_slice(&(message.len() as i32).to_be_bytes());
+            bundle.extend_from_slice(message);
+        }
+
+        let messages = parse_packet(&bundle).unwrap();
+        assert_eq!(vec!["/a", "/b"], messages.iter()
+            .map(|message| message.address.as_str()).collect::<Vec<_>>());
+        assert_eq!(Some(2.0), messages[1].arguments[0].as_f32());
+    }
+
+    #[test]
+    fn addresses_are_mapped_to_signals() {
+        let (mut nexus, _composition) = start();
+        let fader = ControlSpec {
+            signal: "osc".to_string(),
+            input: Some("freq".to_string()),
+            parameter: None,
+            mul: Some(1000.0),
+            add: Some(100.0)
+        };
+        let addresses = [("/fader/1".to_string(), fader)].into();
+
+        let message = parse_packet(&encode("/fader/1", 0.5)).unwrap();
+        dispatch(&message[0], &addresses, &mut nexus).unwrap();
+        assert_eq!(Some(&600.0), nexus.signal("osc").unwrap()
+            .inputs.as_ref().unwrap().get("freq"));
+
+        let message = parse_packet(&encode("/signal/level/value", 0.25))
+            .unwrap();
+        dispatch(&message[0], &addresses, &mut nexus).unwrap();
+        assert_eq!(Some(&0.25), nexus.signal("level").unwrap()
+            .parameters.as_ref().unwrap().get("value"));
+
+        let message = parse_packet(&encode("/fader/2", 0.5)).unwrap();
+        assert_eq!(Err(OscError::UnmappedAddress("/fader/2".to_string())),
+            dispatch(&message[0], &addresses, &mut nexus));
+    }
+
+    #[test]
+    fn udp_messages_reach_the_graph() {
+        let (nexus, mut composition) = start();
+        let (errors, received) = std::sync::mpsc::channel();
+        let listener = OscListener::start(Arc::new(Mutex::new(nexus)),
+            "127.0.0.1:0", &OscSpec::default(), move |e| {
+                let _ = errors.send(e);
+            }).unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.send_to(&encode("/fader/1", 0.25), listener.address())
+            .unwrap();
+        assert_eq!(Ok(OscError::UnmappedAddress("/fader/1".to_string())),
+            received.recv_timeout(Duration::from_secs(5)));
+
+        client.send_to(&encode("/signal/level/value", 0.5),
+            listener.address()).unwrap();
+
+        // Evaluate the graph until the change arrives.
+        for _ in 0..200 {
+            composition.evaluate();
+            if composition.output(0).unwrap()[0] == 0.5 {
+                assert!(listener.stop().is_ok());
+                return
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        panic!("The OSC message didn't reach the graph");
+    }
+}
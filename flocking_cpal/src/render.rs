@@ -0,0 +1,305 @@
+use std::{convert::TryFrom, error::Error, fmt, fs::File, io, path::Path};
+use std::io::{BufWriter, Write};
+use std::time::Duration;
+use flocking::CompositionSpec;
+use flocking::builder::{self, BuildError};
+use libflock::evaluator::Graph;
+
+// Renders compositions to WAV files without opening any audio devices,
+// running the graph at the environment's sample rate and block size.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WavFormat {
+    Int16,
+    Int24,
+    Float32
+}
+
+impl WavFormat {
+    fn bytes_per_sample(&self) -> usize {
+        match self {
+            WavFormat::Int16 => 2,
+            WavFormat::Int24 => 3,
+            WavFormat::Float32 => 4
+        }
+    }
+
+    fn format_tag(&self) -> u16 {
+        match self {
+            WavFormat::Int16 | WavFormat::Int24 => 1, // WAVE_FORMAT_PCM
+            WavFormat::Float32 => 3 // WAVE_FORMAT_IEEE_FLOAT
+        }
+    }
+
+    fn write_sample<W: Write>(&self, writer: &mut W, sample: f32) ->
+        io::Result<()> {
+        let clamped = sample.clamp(-1.0, 1.0);
+
+        match self {
+            WavFormat::Int16 => writer.write_all(
+                &((clamped * i16::MAX as f32).round() as i16).to_le_bytes()),
+            WavFormat::Int24 => {
+                let value = (clamped * 8_388_607.0).round() as i32;
+                writer.write_all(&value.to_le_bytes()[0..3])
+            },
+            WavFormat::Float32 => writer.write_all(&sample.to_le_bytes())
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RenderError {
+    Build(BuildError),
+    Io(io::Error),
+    // The rendering is too long to fit in a WAV file.
+    TooLong
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::Build(e) =>
+                write!(f, "Unable to build the composition. {}", e),
+            RenderError::Io(e) =>
+                write!(f, "Unable to write the rendering. {}", e),
+            RenderError::TooLong =>
+                write!(f, "The rendering is too long for a WAV file.")
+        }
+    }
+}
+
+impl Error for RenderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RenderError::Build(e) => Some(e),
+            RenderError::Io(e) => Some(e),
+            RenderError::TooLong => None
+        }
+    }
+}
+
+impl From<BuildError> for RenderError {
+    fn from(e: BuildError) -> Self {
+        RenderError::Build(e)
+    }
+}
+
+impl From<io::Error> for RenderError {
+    fn from(e: io::Error) -> Self {
+        RenderError::Io(e)
+    }
+}
+
+fn write_header<W: Write>(
+    writer: &mut W,
+    format: WavFormat,
+    num_channels: usize,
+    sample_rate: u32,
+    num_frames: usize
+) -> Result<(), RenderError> {
+    let block_align = num_channels * format.bytes_per_sample();
+    let data_size = u32::try_from(num_frames * block_align)
+        .map_err(|_| RenderError::TooLong)?;
+
+    // Non-PCM formats must include a fact chunk.
+    let is_float = format == WavFormat::Float32;
+    let fmt_size: u32 = if is_float { 18 } else { 16 };
+    let fact_size: u32 = if is_float { 12 } else { 0 };
+    // The data chunk is padded to an even length,
+    // which its own size doesn't include but the RIFF chunk's does.
+    let riff_size = (4 + 8 + fmt_size + fact_size + 8 + data_size % 2)
+        .checked_add(data_size).ok_or(RenderError::TooLong)?;
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&riff_size.to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&fmt_size.to_le_bytes())?;
+    writer.write_all(&format.format_tag().to_le_bytes())?;
+    writer.write_all(&(num_channels as u16).to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    writer.write_all(&(block_align as u16).to_le_bytes())?;
+    writer.write_all(&(format.bytes_per_sample() as u16 * 8).to_le_bytes())?;
+
+    if is_float {
+        writer.write_all(&0_u16.to_le_bytes())?;
+        writer.write_all(b"fact")?;
+        writer.write_all(&4_u32.to_le_bytes())?;
+        writer.write_all(&(num_frames as u32).to_le_bytes())?;
+    }
+
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?;
+
+    Ok(())
+}
+
+// Writes num_frames of the graph's output as a WAV stream,
+// evaluating the graph one block at a time.
+pub fn render_graph<G: Graph, W: Write>(
+    graph: &mut G,
+    writer: &mut W,
+    format: WavFormat,
+    num_channels: usize,
+    sample_rate: u32,
+    block_size: usize,
+    num_frames: usize
+) -> Result<(), RenderError> {
+    write_header(writer, format, num_channels, sample_rate, num_frames)?;
+
+    let mut remaining = num_frames;
+    while remaining > 0 {
+        graph.evaluate();
+
+        let frames = remaining.min(block_size);
+        for i in 0..frames {
+            for channel in 0..num_channels {
+                let sample = graph.output(channel)
+                    .map_or(0.0, |buffer| buffer[i]);
+                format.write_sample(writer, sample)?;
+            }
+        }
+
+        remaining -= frames;
+    }
+
+    if (num_frames * num_channels * format.bytes_per_sample()) % 2 == 1 {
+        writer.write_all(&[0])?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+// Renders the specified duration of the composition to a WAV file.
+pub fn render(
+    composition: &CompositionSpec,
+    duration: Duration,
+    path: &Path,
+    format: WavFormat
+) -> Result<(), RenderError> {
+    let settings = composition.environment.audio_settings();
+    let mut graph = builder::build(composition, settings)?;
+    let num_frames = (duration.as_secs_f64() *
+        settings.sample_rate as f64).round() as usize;
+
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    render_graph(&mut graph, &mut writer, format, settings.num_channels,
+        settings.sample_rate as u32, settings.block_size, num_frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flocking::{EnvironmentSettings, SignalSpec};
+    use std::collections::HashMap;
+
+    fn constant_composition(value: f32) -> CompositionSpec {
+        let mut level = SignalSpec::new("value");
+        level.parameters = Some([("value".to_string(), value)].into());
+
+        CompositionSpec {
+            environment: EnvironmentSettings {
+                sample_rate: Some(8000),
+                num_output_channels: Some(2),
+                block_size: Some(3),
+                ..Default::default()
+            },
+            signals: Some([("level".to_string(), level)].into()),
+            connections: Some(HashMap::new()),
+            output: Some("level".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn render_bytes(value: f32, format: WavFormat, num_frames: usize) ->
+        Vec<u8> {
+        let composition = constant_composition(value);
+        let settings = composition.environment.audio_settings();
+        let mut graph = builder::build(&composition, settings).unwrap();
+
+        let mut bytes = Vec::new();
+        render_graph(&mut graph, &mut bytes, format, settings.num_channels,
+            settings.sample_rate as u32, settings.block_size, num_frames)
+            .unwrap();
+
+        bytes
+    }
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes([bytes[offset], bytes[offset + 1],
+            bytes[offset + 2], bytes[offset + 3]])
+    }
+
+    #[test]
+    fn int16_header_and_samples() {
+        let bytes = render_bytes(0.5, WavFormat::Int16, 10);
+
+        assert_eq!(b"RIFF", &bytes[0..4]);
+        assert_eq!(bytes.len() as u32 - 8, u32_at(&bytes, 4));
+        assert_eq!(b"WAVE", &bytes[8..12]);
+        assert_eq!(1, u16_at(&bytes, 20), "PCM");
+        assert_eq!(2, u16_at(&bytes, 22), "Two channels");
+        assert_eq!(8000, u32_at(&bytes, 24));
+        assert_eq!(8000 * 4, u32_at(&bytes, 28));
+        assert_eq!(4, u16_at(&bytes, 32));
+        assert_eq!(16, u16_at(&bytes, 34));
+        assert_eq!(b"data", &bytes[36..40]);
+        assert_eq!(10 * 4, u32_at(&bytes, 40));
+        assert_eq!(44 + 10 * 4, bytes.len(),
+            "Partial blocks are truncated to the requested duration");
+
+        // The Value only has one output channel,
+        // so the second channel is silent.
+        assert_eq!(16384, u16_at(&bytes, 44));
+        assert_eq!(0, u16_at(&bytes, 46));
+        assert_eq!(16384, u16_at(&bytes, 44 + 9 * 4));
+    }
+
+    #[test]
+    fn int24_samples_are_clipped() {
+        let bytes = render_bytes(2.0, WavFormat::Int24, 4);
+
+        assert_eq!(24, u16_at(&bytes, 34));
+        assert_eq!(4 * 6, u32_at(&bytes, 40));
+        assert_eq!([0xff, 0xff, 0x7f], bytes[44..47]);
+        assert_eq!([0, 0, 0], bytes[47..50]);
+    }
+
+    #[test]
+    fn odd_data_chunks_are_padded() {
+        let composition = constant_composition(0.0);
+        let settings = composition.environment.audio_settings();
+        let mut graph = builder::build(&composition, settings).unwrap();
+
+        let mut bytes = Vec::new();
+        render_graph(&mut graph, &mut bytes, WavFormat::Int24, 1,
+            settings.sample_rate as u32, settings.block_size, 3).unwrap();
+
+        assert_eq!(3 * 3, u32_at(&bytes, 40));
+        assert_eq!(44 + 3 * 3 + 1, bytes.len());
+        assert_eq!(0, bytes[53], "The pad byte is zero");
+        assert_eq!(bytes.len() as u32 - 8, u32_at(&bytes, 4));
+    }
+
+    #[test]
+    fn float32_includes_a_fact_chunk() {
+        let bytes = render_bytes(-0.25, WavFormat::Float32, 5);
+
+        assert_eq!(3, u16_at(&bytes, 20), "IEEE float");
+        assert_eq!(32, u16_at(&bytes, 34));
+        assert_eq!(b"fact", &bytes[38..42]);
+        assert_eq!(5, u32_at(&bytes, 46));
+        assert_eq!(b"data", &bytes[50..54]);
+        assert_eq!(5 * 8, u32_at(&bytes, 54));
+        assert_eq!((-0.25_f32).to_le_bytes(), bytes[58..62]);
+    }
+}
@@ -0,0 +1,687 @@
+use std::{io, thread};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex, mpsc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use flocking::{ConnectionSpec, SignalSpec};
+use flocking::nexus::{Change, Nexus, NexusError};
+use serde::Serialize;
+use tungstenite::{Message, WebSocket};
+use tungstenite::protocol::Role;
+
+// Exposes a Nexus over HTTP and WebSocket on localhost,
+// so that running compositions can be controlled
+// by browser tools and scripts.
+//
+//  GET    /composition                        The current CompositionSpec
+//  GET    /signals/<id>                       A SignalSpec
+//  PUT    /signals/<id>                       Creates a signal from a SignalSpec
+//  DELETE /signals/<id>
+//  PUT    /signals/<id>/inputs/<input>        Sets an input to a number
+//  PUT    /signals/<id>/parameters/<param>    Sets a parameter to a number
+//  GET    /connections/<id>                   A ConnectionSpec
+//  PUT    /connections/<id>                   Connects from a ConnectionSpec
+//  DELETE /connections/<id>
+//  PUT    /output                             Plays the signal named by a string
+//  POST   /changes                            Applies a nexus::Change
+//  GET    /events                             Upgrades to a WebSocket
+//
+// WebSocket clients receive each Change as JSON once it has been made,
+// and may send Changes of their own.
+//
+// Since any web page can send requests to localhost, requests must
+// name the server's own loopback address as their Host, come from
+// a loopback Origin if they have one, and send their bodies as JSON,
+// which pages on other origins can't do without the server's consent.
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:7733";
+
+// How often idle WebSocket connections check for events to send.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// How long a client may take to send each part of its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+const LOOPBACK_HOSTS: [&str; 3] = ["localhost", "127.0.0.1", "[::1]"];
+
+type Subscribers = Arc<Mutex<Vec<mpsc::Sender<String>>>>;
+
+type ErrorHandler = Arc<dyn Fn(io::Error) + Send + Sync>;
+
+#[derive(Clone)]
+struct Shared {
+    // The port that clients must address in their Host header.
+    port: u16,
+    nexus: Arc<Mutex<Nexus>>,
+    subscribers: Subscribers,
+    running: Arc<AtomicBool>,
+    // Called with the error of each connection that fails,
+    // e.g. because its client stopped responding.
+    on_error: ErrorHandler
+}
+
+pub struct NexusServer {
+    address: SocketAddr,
+    shared: Shared,
+    thread: Option<thread::JoinHandle<()>>
+}
+
+impl NexusServer {
+    // Starts serving the Nexus on a loopback address.
+    // Use port 0 to have the operating system pick a port.
+    // Connections that fail are reported to on_error,
+    // which is called on the connection's thread.
+    pub fn start<A, F>(nexus: Arc<Mutex<Nexus>>, address: A, on_error: F) ->
+        io::Result<NexusServer>
+        where A: ToSocketAddrs, F: Fn(io::Error) + Send + Sync + 'static {
+        let address = address.to_socket_addrs()?.next().ok_or_else(||
+            io::Error::new(io::ErrorKind::InvalidInput, "No address given."))?;
+        if !address.ip().is_loopback() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                "The Nexus server only listens on localhost."))
+        }
+
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let shared = Shared {
+            port: address.port(),
+            nexus,
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+            running: Arc::new(AtomicBool::new(true)),
+            on_error: Arc::new(on_error)
+        };
+
+        let listener_shared = shared.clone();
+        let thread = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if !listener_shared.running.load(Ordering::Acquire) {
+                    break;
+                }
+
+                if let Ok(stream) = stream {
+                    let shared = listener_shared.clone();
+                    thread::spawn(move || {
+                        let on_error = shared.on_error.clone();
+                        if let Err(e) = handle_connection(stream, shared) {
+                            on_error(e);
+                        }
+                    });
+                }
+            }
+        });
+
+        Ok(NexusServer {
+            address,
+            shared,
+            thread: Some(thread)
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn nexus(&self) -> Arc<Mutex<Nexus>> {
+        self.shared.nexus.clone()
+    }
+}
+
+impl Drop for NexusServer {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::Release);
+
+        // Wake the listener so that it notices it should stop.
+        let _ = TcpStream::connect(self.address);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    content_length: usize,
+    body: Vec<u8>
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+struct Response {
+    status: u16,
+    body: String
+}
+
+impl Response {
+    fn json<T: Serialize>(status: u16, value: &T) -> Response {
+        match serde_json::to_string(value) {
+            Ok(body) => Response { status, body },
+            Err(e) => Response::error(500, &e.to_string())
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response {
+            status,
+            body: serde_json::json!({ "error": message }).to_string()
+        }
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            201 => "Created",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            409 => "Conflict",
+            413 => "Payload Too Large",
+            415 => "Unsupported Media Type",
+            422 => "Unprocessable Entity",
+            503 => "Service Unavailable",
+            _ => "Internal Server Error"
+        };
+
+        write!(writer, "HTTP/1.1 {} {}\r\n\
+            Content-Type: application/json\r\n\
+            Content-Length: {}\r\n\
+            Connection: close\r\n\r\n{}",
+            self.status, reason, self.body.len(), self.body)?;
+        writer.flush()
+    }
+}
+
+// Reads the request line and headers; the body is read
+// once the request has been checked.
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Request> {
+    let invalid = |message: &str| io::Error::new(
+        io::ErrorKind::InvalidData, message.to_string());
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().ok_or_else(|| invalid("No method."))?
+        .to_string();
+    let path = parts.next().ok_or_else(|| invalid("No path."))?
+        .to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        let (name, value) = header.split_once(':')
+            .ok_or_else(|| invalid("Malformed header."))?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    let mut request = Request {
+        method,
+        path,
+        headers,
+        content_length: 0,
+        body: Vec::new()
+    };
+    request.content_length = match request.header("Content-Length") {
+        Some(length) => length.parse::<usize>()
+            .map_err(|_| invalid("Malformed Content-Length."))?,
+        None => 0
+    };
+
+    Ok(request)
+}
+
+fn is_loopback_host(host: &str) -> bool {
+    LOOPBACK_HOSTS.contains(&host)
+}
+
+// Rejects requests that a web page on another origin could have made,
+// and bodies that are too large or not JSON.
+fn check_request(request: &Request, shared: &Shared) -> Result<(), Response> {
+    let host = request.header("Host").unwrap_or_default();
+    let is_own_host = host.rsplit_once(':').is_some_and(|(name, port)|
+        is_loopback_host(name) && port == shared.port.to_string());
+    if !is_own_host {
+        return Err(Response::error(403, "Unknown Host."))
+    }
+
+    if let Some(origin) = request.header("Origin") {
+        let authority = origin.strip_prefix("http://")
+            .or_else(|| origin.strip_prefix("https://"))
+            .unwrap_or_default();
+        let name = match authority.find(']') {
+            Some(end) => &authority[..=end],
+            None => authority.split(':').next().unwrap_or_default()
+        };
+        if !is_loopback_host(name) {
+            return Err(Response::error(403, "Cross-origin request."))
+        }
+    }
+
+    if request.content_length > MAX_BODY_SIZE {
+        return Err(Response::error(413, "The body is too large."))
+    }
+
+    let is_json = request.header("Content-Type").is_some_and(|value|
+        value.split(';').next().unwrap_or_default().trim()
+            .eq_ignore_ascii_case("application/json"));
+    if matches!(request.method.as_str(), "PUT" | "POST") && !is_json {
+        return Err(Response::error(415, "The body must be JSON."))
+    }
+
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, shared: Shared) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = read_request(&mut reader)?;
+
+    if let Err(response) = check_request(&request, &shared) {
+        let mut stream = stream;
+        return response.write_to(&mut stream)
+    }
+    request.body.resize(request.content_length, 0);
+    reader.read_exact(&mut request.body)?;
+
+    let is_upgrade = request.header("Upgrade")
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+    if request.method == "GET" && request.path == "/events" && is_upgrade {
+        return serve_websocket(stream, &request, shared)
+    }
+
+    let mut stream = stream;
+    route(&request, &shared).write_to(&mut stream)
+}
+
+fn parse<'a, T: serde::Deserialize<'a>>(body: &'a [u8]) ->
+    Result<T, Response> {
+    serde_json::from_slice(body)
+        .map_err(|e| Response::error(400, &e.to_string()))
+}
+
+fn route(request: &Request, shared: &Shared) -> Response {
+    let segments: Vec<&str> = request.path.trim_matches('/')
+        .split('/').collect();
+    let body = &request.body;
+
+    let change = match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["composition"]) => {
+            let nexus = shared.nexus.lock().unwrap();
+            return Response::json(200, nexus.spec())
+        },
+        ("GET", ["signals", id]) => {
+            let nexus = shared.nexus.lock().unwrap();
+            return match nexus.signal(id) {
+                Some(spec) => Response::json(200, spec),
+                None => not_found(NexusError::UnknownSignal(id.to_string()))
+            }
+        },
+        ("GET", ["connections", id]) => {
+            let nexus = shared.nexus.lock().unwrap();
+            return match nexus.connection(id) {
+                Some(spec) => Response::json(200, spec),
+                None => not_found(
+                    NexusError::UnknownConnection(id.to_string()))
+            }
+        },
+        ("PUT", ["signals", id]) => parse::<SignalSpec>(body)
+            .map(|spec| Change::CreateSignal { id: id.to_string(), spec }),
+        ("DELETE", ["signals", id]) =>
+            Ok(Change::RemoveSignal { id: id.to_string() }),
+        ("PUT", ["signals", id, "inputs", input]) => parse::<f32>(body)
+            .map(|value| Change::SetInput {
+                signal: id.to_string(),
+                input: input.to_string(),
+                value
+            }),
+        ("PUT", ["signals", id, "parameters", parameter]) =>
+            parse::<f32>(body).map(|value| Change::SetParameter {
+                signal: id.to_string(),
+                parameter: parameter.to_string(),
+                value
+            }),
+        ("PUT", ["connections", id]) => parse::<ConnectionSpec>(body)
+            .map(|connection| Change::Connect {
+                id: id.to_string(),
+                connection
+            }),
+        ("DELETE", ["connections", id]) =>
+            Ok(Change::Disconnect { id: id.to_string() }),
+        ("PUT", ["output"]) => parse::<String>(body)
+            .map(|signal| Change::SetOutput { signal }),
+        ("POST", ["changes"]) => parse::<Change>(body),
+        _ => Err(Response::error(404, "Not found."))
+    };
+
+    let change = match change {
+        Ok(change) => change,
+        Err(response) => return response
+    };
+
+    let status = match change {
+        Change::CreateSignal { .. } | Change::Connect { .. } => 201,
+        _ => 200
+    };
+
+    match apply(change, shared) {
+        Ok(change) => Response::json(status, &change),
+        Err(e) => error_response(e)
+    }
+}
+
+fn not_found(e: NexusError) -> Response {
+    Response::error(404, &e.to_string())
+}
+
+fn error_response(e: NexusError) -> Response {
+    let status = match e {
+        NexusError::UnknownSignal(_) | NexusError::UnknownConnection(_) =>
+            404,
+        NexusError::DuplicateSignal(_) |
+            NexusError::DuplicateConnection(_) |
+            NexusError::InputConnected(..) |
+            NexusError::TooManySignals => 409,
+        NexusError::Invalid(_) => 422,
+        NexusError::QueueFull => 503
+    };
+
+    Response::error(status, &e.to_string())
+}
+
+// Applies the change to the Nexus and, if it succeeds,
+// reports it to every WebSocket client.
+fn apply(change: Change, shared: &Shared) -> Result<Change, NexusError> {
+    let errors = {
+        let mut nexus = shared.nexus.lock().unwrap();
+        nexus.apply(change.clone())?;
+        nexus.poll()
+    };
+
+    if let Ok(message) = serde_json::to_string(&change) {
+        broadcast(&shared.subscribers, message);
+    }
+
+    // Report any changes that the graph itself rejected.
+    for e in errors {
+        broadcast(&shared.subscribers,
+            serde_json::json!({ "error": e.to_string() }).to_string());
+    }
+
+    Ok(change)
+}
+
+fn broadcast(subscribers: &Subscribers, message: String) {
+    subscribers.lock().unwrap()
+        .retain(|subscriber| subscriber.send(message.clone()).is_ok());
+}
+
+fn serve_websocket(
+    mut stream: TcpStream,
+    request: &Request,
+    shared: Shared
+) -> io::Result<()> {
+    let key = match request.header("Sec-WebSocket-Key") {
+        Some(key) => key,
+        None => return Response::error(400, "Missing Sec-WebSocket-Key.")
+            .write_to(&mut stream)
+    };
+
+    write!(stream, "HTTP/1.1 101 Switching Protocols\r\n\
+        Upgrade: websocket\r\n\
+        Connection: Upgrade\r\n\
+        Sec-WebSocket-Accept: {}\r\n\r\n",
+        tungstenite::handshake::derive_accept_key(key.as_bytes()))?;
+    stream.flush()?;
+
+    let (sender, receiver) = mpsc::channel();
+    shared.subscribers.lock().unwrap().push(sender);
+
+    // Reads time out so that the connection can also send events.
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+
+    while shared.running.load(Ordering::Acquire) {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                let result = serde_json::from_str::<Change>(&text)
+                    .map_err(|e| e.to_string())
+                    .and_then(|change| apply(change, &shared)
+                        .map_err(|e| e.to_string()));
+
+                // Successful changes are reported by the broadcast.
+                if let Err(message) = result {
+                    let error = serde_json::json!({ "error": message });
+                    send(&mut socket, error.to_string())?;
+                }
+            },
+            Ok(Message::Close(_)) => break,
+            Ok(_) => (),
+            Err(tungstenite::Error::Io(e)) if matches!(e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => (),
+            Err(tungstenite::Error::ConnectionClosed) => break,
+            Err(e) => return Err(io::Error::other(e))
+        }
+
+        while let Ok(message) = receiver.try_recv() {
+            send(&mut socket, message)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn send(socket: &mut WebSocket<TcpStream>, message: String) ->
+    io::Result<()> {
+    socket.send(Message::Text(message))
+        .map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{render_graph, WavFormat};
+    use flocking::{CompositionSpec, EnvironmentSettings};
+    use flocking::builder::Composition;
+
+    fn start() -> (NexusServer, Composition) {
+        let spec = CompositionSpec {
+            environment: EnvironmentSettings {
+                sample_rate: Some(8000),
+                block_size: Some(8),
+                num_output_channels: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let settings = spec.environment.audio_settings();
+        let (nexus, composition) = Nexus::new(spec, settings).unwrap();
+
+        let nexus = Arc::new(Mutex::new(nexus));
+
+        (NexusServer::start(nexus, "127.0.0.1:0", |_| ()).unwrap(),
+            composition)
+    }
+
+    // Sends the request's line and headers, and the body,
+    // and returns the response's status and body.
+    fn exchange(server: &NexusServer, head: &str, body: &str) ->
+        (u16, serde_json::Value) {
+        let mut stream = TcpStream::connect(server.address()).unwrap();
+        write!(stream, "{}Content-Length: {}\r\n\r\n{}",
+            head, body.len(), body).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    fn request(server: &NexusServer, method: &str, path: &str, body: &str) ->
+        (u16, serde_json::Value) {
+        exchange(server, &format!("{} {} HTTP/1.1\r\nHost: localhost:{}\r\n\
+            Content-Type: application/json\r\n",
+            method, path, server.address().port()), body)
+    }
+
+    // Renders a block of the composition offline
+    // and returns its first sample.
+    fn render_first_sample(composition: &mut Composition) -> f32 {
+        let mut bytes = Vec::new();
+        render_graph(composition, &mut bytes, WavFormat::Float32,
+            1, 8000, 8, 1).unwrap();
+        let sample = &bytes[bytes.len() - 4..];
+
+        f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]])
+    }
+
+    #[test]
+    fn signals_are_controlled_over_http() {
+        let (server, mut composition) = start();
+
+        let (status, _) = request(&server, "PUT", "/signals/level",
+            r#"{ "type": "value", "parameters": { "value": 0.5 } }"#);
+        assert_eq!(201, status);
+        assert_eq!(200, request(&server, "PUT", "/output", r#""level""#).0);
+        assert_eq!(0.5, render_first_sample(&mut composition));
+
+        let (status, _) = request(&server, "PUT",
+            "/signals/level/parameters/value", "0.25");
+        assert_eq!(200, status);
+        assert_eq!(0.25, render_first_sample(&mut composition));
+
+        let (status, spec) = request(&server, "GET", "/signals/level", "");
+        assert_eq!(200, status);
+        assert_eq!(0.25, spec["parameters"]["value"]);
+
+        let (status, composition_spec) =
+            request(&server, "GET", "/composition", "");
+        assert_eq!(200, status);
+        assert_eq!("level", composition_spec["output"]);
+
+        let (status, error) = request(&server, "PUT", "/connections/c",
+            r#"{ "source": "lfo", "target": "level", "input": "freq" }"#);
+        assert_eq!(404, status);
+        assert!(error["error"].is_string());
+
+        assert_eq!(409, request(&server, "PUT", "/signals/level",
+            r#"{ "type": "sine" }"#).0);
+        assert_eq!(400, request(&server, "PUT", "/signals/osc", "{").0);
+        assert_eq!(200, request(&server, "DELETE", "/signals/level", "").0);
+        assert_eq!(404, request(&server, "GET", "/signals/level", "").0);
+    }
+
+    #[test]
+    fn changes_are_streamed_over_websockets() {
+        let (server, mut composition) = start();
+        let stream = TcpStream::connect(server.address()).unwrap();
+        let url = format!("ws://{}/events", server.address());
+        let (mut socket, _) = tungstenite::client(url.as_str(), stream)
+            .unwrap();
+
+        socket.send(Message::Text(r#"{
+            "op": "create_signal", "id": "level",
+            "spec": { "type": "value", "parameters": { "value": 0.75 } }
+        }"#.to_string())).unwrap();
+
+        let event: Change = match socket.read().unwrap() {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            other => panic!("Expected a change: {:?}", other)
+        };
+        assert!(matches!(event, Change::CreateSignal { ref id, .. }
+            if id == "level"));
+
+        // Changes made over HTTP are also streamed.
+        request(&server, "PUT", "/output", r#""level""#);
+        let event: Change = match socket.read().unwrap() {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            other => panic!("Expected a change: {:?}", other)
+        };
+        assert_eq!(Change::SetOutput { signal: "level".to_string() }, event);
+        assert_eq!(0.75, render_first_sample(&mut composition));
+
+        socket.send(Message::Text(r#"{ "op": "remove_signal", "id": "x" }"#
+            .to_string())).unwrap();
+        match socket.read().unwrap() {
+            Message::Text(text) => assert!(text.contains("error")),
+            other => panic!("Expected an error: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn requests_from_other_sites_are_rejected() {
+        let (server, _composition) = start();
+        let host = format!("Host: {}\r\n", server.address());
+        let change = r#"{ "op": "create_signal", "id": "level",
+            "spec": { "type": "value" } }"#;
+        let post = |headers: &str| exchange(&server,
+            &format!("POST /changes HTTP/1.1\r\n{}", headers), change).0;
+
+        assert_eq!(403, post(&format!("{}Origin: http://example.com\r\n\
+            Content-Type: application/json\r\n", host)));
+        assert_eq!(403, post(&format!("Host: example.com:{}\r\n\
+            Content-Type: application/json\r\n", server.address().port())));
+        assert_eq!(403, post("Host: localhost\r\n\
+            Content-Type: application/json\r\n"));
+        assert_eq!(415, post(&format!("{}Content-Type: text/plain\r\n",
+            host)));
+        assert_eq!(403, exchange(&server, &format!("GET /events HTTP/1.1\r\n\
+            {}Origin: http://example.com\r\nUpgrade: websocket\r\n\
+            Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n", host), "").0);
+        assert_eq!(201, post(&format!("{}Origin: http://localhost:3000\r\n\
+            Content-Type: application/json; charset=utf-8\r\n", host)));
+
+        let mut stream = TcpStream::connect(server.address()).unwrap();
+        write!(stream, "PUT /signals/big HTTP/1.1\r\n{}\
+            Content-Type: application/json\r\n\
+            Content-Length: {}\r\n\r\n", host, MAX_BODY_SIZE + 1).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 413"), "{}", response);
+    }
+
+    #[test]
+    fn failed_connections_are_reported() {
+        let spec = CompositionSpec::default();
+        let settings = spec.environment.audio_settings();
+        let (nexus, _composition) = Nexus::new(spec, settings).unwrap();
+        let (errors, received) = mpsc::channel();
+        let errors = Mutex::new(errors);
+        let server = NexusServer::start(Arc::new(Mutex::new(nexus)),
+            "127.0.0.1:0", move |e| {
+                let _ = errors.lock().unwrap().send(e.kind());
+            }).unwrap();
+
+        let mut stream = TcpStream::connect(server.address()).unwrap();
+        stream.write_all(b"nonsense\r\n\r\n").unwrap();
+
+        assert_eq!(Ok(io::ErrorKind::InvalidData),
+            received.recv_timeout(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn only_loopback_addresses_are_served() {
+        let spec = CompositionSpec::default();
+        let settings = spec.environment.audio_settings();
+        let (nexus, _composition) = Nexus::new(spec, settings).unwrap();
+
+        assert!(NexusServer::start(Arc::new(Mutex::new(nexus)), "0.0.0.0:0",
+            |_| ()).is_err());
+    }
+}
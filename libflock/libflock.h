@@ -4,6 +4,14 @@
 #include <ostream>
 #include <new>
 
+#if defined(LOWMEM)
+static const uintptr_t MAX_SIGNALS = 16;
+#endif
+
+#if !defined(LOWMEM)
+static const uintptr_t MAX_SIGNALS = 64;
+#endif
+
 static const float PI = 3.14159265358979323846264338327950288;
 
 static const float TWO_PI = (2.0 * PI);
@@ -24,6 +32,32 @@ static const uintptr_t MAX_CHANNEL_COUNT = 2;
 static const uintptr_t MAX_CHANNEL_COUNT = 8;
 #endif
 
+static const uintptr_t MAX_ENVELOPE_SEGMENTS = 8;
+
+static const uintptr_t MAX_OUTPUT_SOURCES = 8;
+
+enum class Curve {
+  Linear = 0,
+  Exponential = 1,
+  Custom = 2,
+};
+
+enum class FilterResponse {
+  LowPass = 0,
+  HighPass = 1,
+  BandPass = 2,
+  Notch = 3,
+  Peaking = 4,
+  LowShelf = 5,
+  HighShelf = 6,
+};
+
+enum class Interpolation {
+  Truncate = 0,
+  Linear = 1,
+  Cubic = 2,
+};
+
 struct MonoBuffer {
   float samples[MAX_BLOCK_SIZE];
 };
@@ -32,6 +66,13 @@ struct MultichannelBuffer {
   float channels[MAX_CHANNEL_COUNT][MAX_BLOCK_SIZE];
 };
 
+struct Connection {
+  const float *buffer;
+  float value;
+  uintptr_t step_size;
+  uintptr_t offset;
+};
+
 struct AudioSettings {
   float sample_rate;
   uintptr_t block_size;
@@ -50,10 +91,10 @@ struct Value {
 };
 
 struct SineInputs {
-  MonoBuffer freq;
-  MonoBuffer phase_offset;
-  MonoBuffer mul;
-  MonoBuffer add;
+  Connection freq;
+  Connection phase_offset;
+  Connection mul;
+  Connection add;
 };
 
 struct Sine {
@@ -63,8 +104,160 @@ struct Sine {
   float phase_accumulator;
 };
 
+struct OscillatorInputs {
+  Connection freq;
+  Connection phase_offset;
+  Connection mul;
+  Connection add;
+};
+
+struct Saw {
+  AudioSettings settings;
+  OscillatorInputs inputs;
+  MonoBuffer output;
+  float phase_accumulator;
+};
+
+struct Square {
+  AudioSettings settings;
+  OscillatorInputs inputs;
+  MonoBuffer output;
+  float phase_accumulator;
+};
+
+struct Triangle {
+  AudioSettings settings;
+  OscillatorInputs inputs;
+  MonoBuffer output;
+  float phase_accumulator;
+};
+
+struct PulseInputs {
+  Connection freq;
+  Connection phase_offset;
+  Connection mul;
+  Connection add;
+  Connection width;
+};
+
+struct Pulse {
+  AudioSettings settings;
+  PulseInputs inputs;
+  MonoBuffer output;
+  float phase_accumulator;
+};
+
+struct WavetableInputs {
+  Connection freq;
+  Connection phase_offset;
+  Connection mul;
+  Connection add;
+  Connection morph;
+};
+
+struct WavetableParameters {
+  float interpolation;
+};
+
+struct Wavetable {
+  AudioSettings settings;
+  WavetableInputs inputs;
+  WavetableParameters parameters;
+  const float *tables;
+  uintptr_t table_length;
+  uintptr_t num_tables;
+  MonoBuffer output;
+  float phase_accumulator;
+};
+
+struct NoiseInputs {
+  Connection mul;
+  Connection add;
+};
+
+struct WhiteNoise {
+  AudioSettings settings;
+  NoiseInputs inputs;
+  uint64_t seed;
+  MonoBuffer output;
+};
+
+struct PinkNoise {
+  AudioSettings settings;
+  NoiseInputs inputs;
+  uint64_t seed;
+  float filter[7];
+  MonoBuffer output;
+};
+
+struct BrownNoise {
+  AudioSettings settings;
+  NoiseInputs inputs;
+  uint64_t seed;
+  float last_sample;
+  MonoBuffer output;
+};
+
+struct EnvelopeInputs {
+  Connection gate;
+  Connection mul;
+  Connection add;
+};
+
+struct Segment {
+  float time;
+  float level;
+  Curve curve;
+  float curvature;
+};
+
+struct Envelope {
+  AudioSettings settings;
+  EnvelopeInputs inputs;
+  float start_level;
+  Segment segments[MAX_ENVELOPE_SEGMENTS];
+  uintptr_t num_segments;
+  int32_t sustain_point;
+  int32_t loop_point;
+  uintptr_t segment;
+  float elapsed;
+  float segment_start;
+  float level;
+  bool sustaining;
+  float last_gate;
+  MonoBuffer output;
+};
+
+struct BiquadInputs {
+  Connection source;
+  Connection cutoff;
+  Connection q;
+  Connection gain;
+  Connection mul;
+  Connection add;
+};
+
+struct BiquadCoefficients {
+  float a1;
+  float a2;
+  float a3;
+  float m0;
+  float m1;
+  float m2;
+};
+
+struct Biquad {
+  AudioSettings settings;
+  BiquadInputs inputs;
+  FilterResponse response;
+  BiquadCoefficients coefficients;
+  float designed_for[3];
+  float state[2];
+  MonoBuffer output;
+};
+
 struct FanInputs {
-  MonoBuffer source;
+  Connection source;
 };
 
 struct Fan {
@@ -73,6 +266,27 @@ struct Fan {
   MultichannelBuffer output;
 };
 
+struct AudioOutInputs {
+  Connection sources[MAX_OUTPUT_SOURCES];
+};
+
+struct AudioOutParameters {
+  float channels[MAX_OUTPUT_SOURCES];
+};
+
+struct AudioOut {
+  AudioSettings settings;
+  AudioOutInputs inputs;
+  AudioOutParameters parameters;
+  MultichannelBuffer output;
+};
+
+struct AudioIn {
+  AudioSettings settings;
+  const MultichannelBuffer *source;
+  MultichannelBuffer output;
+};
+
 extern "C" {
 
 MonoBuffer MonoBuffer_new_with_value(float value);
@@ -83,6 +297,10 @@ MultichannelBuffer MultichannelBuffer_new_with_value(float value);
 
 MultichannelBuffer MultichannelBuffer_new_silent();
 
+Connection Connection_new_constant(float value);
+
+Connection Connection_new(const float *buffer, uintptr_t step_size);
+
 Value Value_new(AudioSettings settings);
 
 void Value_generate(Value *value);
@@ -91,6 +309,68 @@ Sine Sine_new(AudioSettings settings);
 
 void Sine_generate(Sine *sine);
 
+Saw Saw_new(AudioSettings settings);
+
+void Saw_generate(Saw *saw);
+
+Square Square_new(AudioSettings settings);
+
+void Square_generate(Square *square);
+
+Triangle Triangle_new(AudioSettings settings);
+
+void Triangle_generate(Triangle *triangle);
+
+Pulse Pulse_new(AudioSettings settings);
+
+void Pulse_generate(Pulse *pulse);
+
+Wavetable Wavetable_new(AudioSettings settings,
+                        const float *tables,
+                        uintptr_t table_length,
+                        uintptr_t num_tables);
+
+void Wavetable_generate(Wavetable *wavetable);
+
+WhiteNoise WhiteNoise_new(AudioSettings settings, uint64_t seed);
+
+void WhiteNoise_generate(WhiteNoise *noise);
+
+PinkNoise PinkNoise_new(AudioSettings settings, uint64_t seed);
+
+void PinkNoise_generate(PinkNoise *noise);
+
+BrownNoise BrownNoise_new(AudioSettings settings, uint64_t seed);
+
+void BrownNoise_generate(BrownNoise *noise);
+
+Envelope Envelope_new(AudioSettings settings,
+                      float start_level,
+                      const Segment *segments,
+                      uintptr_t num_segments);
+
+Envelope Envelope_adsr(AudioSettings settings,
+                       float attack,
+                       float decay,
+                       float sustain,
+                       float release);
+
+void Envelope_generate(Envelope *envelope);
+
+Biquad Biquad_new(AudioSettings settings, FilterResponse response);
+
+void Biquad_generate(Biquad *biquad);
+
+Fan Fan_new(AudioSettings settings);
+
 void Fan_generate(Fan *fan);
 
+AudioOut AudioOut_new(AudioSettings settings);
+
+void AudioOut_generate(AudioOut *audio_out);
+
+AudioIn AudioIn_new(AudioSettings settings, const MultichannelBuffer *source);
+
+void AudioIn_generate(AudioIn *audio_in);
+
 } // extern "C"
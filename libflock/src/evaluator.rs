@@ -0,0 +1,1256 @@
+use core::fmt;
+use crate::queue::Consumer;
+use crate::signals::{Connection, Signal, MAX_BLOCK_SIZE};
+
+#[cfg(feature = "lowmem")]
+pub const MAX_SIGNALS: usize = 16;
+#[cfg(not(feature = "lowmem"))]
+pub const MAX_SIGNALS: usize = 64;
+
+pub type SignalId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvaluatorError {
+    TooManySignals,
+    UnknownSignal(SignalId),
+    UnknownInput(SignalId, usize),
+    UnknownOutput(SignalId, usize),
+    UnknownParameter(SignalId, usize),
+    SignalExists(SignalId),
+    InputConnected(SignalId, usize)
+}
+
+impl fmt::Display for EvaluatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvaluatorError::TooManySignals => write!(f,
+                "The graph can't hold more than {} signals.", MAX_SIGNALS),
+            EvaluatorError::UnknownSignal(id) => write!(f,
+                "There is no signal with ID {}.", id),
+            EvaluatorError::UnknownInput(id, input) => write!(f,
+                "Signal {} has no input at index {}.", id, input),
+            EvaluatorError::UnknownOutput(id, channel) => write!(f,
+                "Signal {} has no output channel {}.", id, channel),
+            EvaluatorError::UnknownParameter(id, parameter) => write!(f,
+                "Signal {} has no parameter at index {}.", id, parameter),
+            EvaluatorError::SignalExists(id) => write!(f,
+                "There is already a signal with ID {}.", id),
+            EvaluatorError::InputConnected(id, input) => write!(f,
+                "Input {} of signal {} is connected to another signal.",
+                input, id)
+        }
+    }
+}
+
+// Changes to a running graph, sent from the main thread
+// and applied by the Evaluator between blocks.
+pub enum Command<'a> {
+    SetParameter { signal: SignalId, parameter: usize, value: f32 },
+    Connect {
+        source: SignalId,
+        channel: usize,
+        target: SignalId,
+        input: usize,
+        step_size: usize
+    },
+    // Sets the input to a constant value,
+    // disconnecting it from any signal.
+    Disconnect { target: SignalId, input: usize, value: f32 },
+    // Changes the value of a constant input
+    // without changing the graph's topology.
+    SetInputValue { target: SignalId, input: usize, value: f32 },
+    // The main thread chooses the ID of signals it adds,
+    // so that it can refer to them in subsequent commands.
+    AddSignal {
+        id: SignalId,
+        signal: &'a mut dyn Signal,
+        control_rate: bool
+    },
+    RemoveSignal(SignalId),
+    SetOutput(SignalId)
+}
+
+// Reports the outcome of commands back to the main thread.
+pub enum Event<'a> {
+    // The signal is no longer used by the Evaluator
+    // and can be freed by its owner.
+    Removed(SignalId, &'a mut dyn Signal),
+    Rejected(EvaluatorError)
+}
+
+// A graph of signals whose output can be drawn block by block,
+// e.g. by an audio environment's callback.
+pub trait Graph: Send {
+    fn evaluate(&mut self);
+    fn output(&self, channel: usize) -> Option<&[f32; MAX_BLOCK_SIZE]>;
+}
+
+// Collects the output of a signal that generates several smaller blocks
+// per block of the graph, so that signals evaluated afterwards
+// can read the whole block.
+pub struct Junction {
+    source: Option<(SignalId, usize)>,
+    used: bool,
+    buffer: [f32; MAX_BLOCK_SIZE]
+}
+
+impl Junction {
+    pub fn new() -> Junction {
+        Junction {
+            source: None,
+            used: false,
+            buffer: [0.0; MAX_BLOCK_SIZE]
+        }
+    }
+}
+
+impl Default for Junction {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A sequence of signals in the evaluation order that are generated
+// together, the specified number of times per block of the graph.
+#[derive(Debug, Clone, Copy)]
+struct Run {
+    start: usize,
+    end: usize,
+    repeats: usize,
+    block_size: usize
+}
+
+// The Evaluator draws samples from a graph of Signals.
+// It doesn't own the Signals themselves (libflock can't allocate),
+// so they must outlive the Evaluator.
+//
+// Signals whose block size is smaller than the graph's, and divides it,
+// are generated several times per block. Consecutive signals in the
+// evaluation order that share a block size are generated together, so that
+// e.g. a single-sample subgraph runs sample by sample. Their inputs from
+// the rest of the graph are read from the matching part of the block,
+// and their outputs are collected in Junctions for the rest of the graph.
+// Signals with any other block size, and control-rate signals,
+// are generated once per block.
+pub struct Evaluator<'a> {
+    signals: [Option<&'a mut dyn Signal>; MAX_SIGNALS],
+    control_rate: [bool; MAX_SIGNALS],
+    order: [SignalId; MAX_SIGNALS],
+    order_len: usize,
+    output: Option<SignalId>,
+    // When unspecified, the block size of the graph's largest signal.
+    block_size: Option<usize>,
+    junctions: &'a mut [Junction],
+    runs: [Run; MAX_SIGNALS],
+    num_runs: usize,
+    run_of: [usize; MAX_SIGNALS],
+    // A bit for each of a signal's first 64 inputs
+    // that reads from outside of its run.
+    offset_inputs: [u64; MAX_SIGNALS],
+    // The number of inputs of each signal (the first index)
+    // that are connected to each signal (the second index).
+    edges: [[u8; MAX_SIGNALS]; MAX_SIGNALS],
+    // Whether the graph has changed since it was last sorted.
+    // Changes are sorted together before the next block is generated,
+    // rather than after each command.
+    unsorted: bool
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new() -> Evaluator<'a> {
+        Evaluator {
+            signals: core::array::from_fn(|_| None),
+            control_rate: [false; MAX_SIGNALS],
+            order: [0; MAX_SIGNALS],
+            order_len: 0,
+            output: None,
+            block_size: None,
+            junctions: &mut [],
+            runs: [Run { start: 0, end: 0, repeats: 1, block_size: 0 };
+                MAX_SIGNALS],
+            num_runs: 0,
+            run_of: [0; MAX_SIGNALS],
+            offset_inputs: [0; MAX_SIGNALS],
+            edges: [[0; MAX_SIGNALS]; MAX_SIGNALS],
+            unsorted: false
+        }
+    }
+
+    // Creates an Evaluator for a graph with the specified block size.
+    // Signals with smaller block sizes need a Junction for each output
+    // channel that is read by signals outside of their run; without one,
+    // those signals read whatever was generated last.
+    pub fn with_block_size(block_size: usize, junctions: &'a mut [Junction]) ->
+        Evaluator<'a> {
+        Evaluator {
+            block_size: Some(block_size.min(MAX_BLOCK_SIZE)),
+            junctions,
+            ..Evaluator::new()
+        }
+    }
+
+    // The number of samples generated by each call to evaluate().
+    pub fn block_size(&self) -> usize {
+        self.block_size.unwrap_or_else(|| self.signals.iter().flatten()
+            .map(|signal| signal.block_size())
+            .max()
+            .unwrap_or(0))
+    }
+
+    // Hands back the Evaluator's Junctions, e.g. so that they can be freed.
+    pub fn release_junctions(&mut self) -> &'a mut [Junction] {
+        let junctions = core::mem::take(&mut self.junctions);
+
+        // Inputs that read from the Junctions read from their sources again.
+        for target in 0..MAX_SIGNALS {
+            for input in 0.. {
+                let connection = match self.signals[target].as_ref()
+                    .and_then(|signal| signal.input(input)) {
+                    Some(connection) => *connection,
+                    None => break
+                };
+                let source = junctions.iter()
+                    .find(|junction| junction.buffer.as_ptr() ==
+                        connection.buffer)
+                    .and_then(|junction| junction.source);
+
+                if let Some((source, channel)) = source {
+                    let buffer = self.signals[source].as_ref()
+                        .and_then(|signal| signal.output(channel))
+                        .map(|buffer| buffer.as_ptr());
+                    if let (Some(buffer), Some(current)) = (buffer,
+                        self.signals[target].as_mut()
+                            .and_then(|signal| signal.input_mut(input))) {
+                        current.buffer = buffer;
+                    }
+                }
+            }
+        }
+        self.sort();
+
+        junctions
+    }
+
+    pub fn add(&mut self, signal: &'a mut dyn Signal) ->
+        Result<SignalId, EvaluatorError> {
+        let id = self.signals.iter().position(|slot| slot.is_none())
+            .ok_or(EvaluatorError::TooManySignals)?;
+        self.signals[id] = Some(signal);
+        self.control_rate[id] = false;
+        self.count_edges(id);
+        self.unsorted = true;
+
+        Ok(id)
+    }
+
+    // Adds a signal with the specified ID.
+    pub fn insert(&mut self, id: SignalId, signal: &'a mut dyn Signal) ->
+        Result<(), EvaluatorError> {
+        match self.signals.get_mut(id) {
+            Some(Some(_)) => Err(EvaluatorError::SignalExists(id)),
+            Some(slot) => {
+                *slot = Some(signal);
+                self.control_rate[id] = false;
+                self.count_edges(id);
+                self.unsorted = true;
+                Ok(())
+            },
+            None => Err(EvaluatorError::TooManySignals)
+        }
+    }
+
+    // Removes the signal from the graph, disconnecting any inputs
+    // that read from it, and hands it back to the caller.
+    pub fn remove(&mut self, id: SignalId) ->
+        Result<&'a mut dyn Signal, EvaluatorError> {
+        if !matches!(self.signals.get(id), Some(Some(_))) {
+            return Err(EvaluatorError::UnknownSignal(id))
+        }
+
+        for target in 0..MAX_SIGNALS {
+            if self.edges[target][id] == 0 {
+                continue;
+            }
+
+            for input in 0.. {
+                let connection = match self.signals[target].as_ref()
+                    .and_then(|signal| signal.input(input)) {
+                    Some(connection) => *connection,
+                    None => break
+                };
+
+                if self.source_of(&connection) == Some(id) {
+                    self.replace_input(target, input,
+                        Connection::new_constant(0.0), None)?;
+                }
+            }
+        }
+        self.edges[id] = [0; MAX_SIGNALS];
+
+        if self.output == Some(id) {
+            self.output = None;
+        }
+
+        let signal = self.signals[id].take()
+            .ok_or(EvaluatorError::UnknownSignal(id))?;
+        self.unsorted = true;
+
+        Ok(signal)
+    }
+
+    pub fn signal(&self, id: SignalId) -> Option<&dyn Signal> {
+        match self.signals.get(id) {
+            Some(Some(signal)) => Some(&**signal),
+            _ => None
+        }
+    }
+
+    // Control-rate signals are generated once per block of the graph,
+    // regardless of their block size.
+    pub fn set_control_rate(&mut self, id: SignalId, control_rate: bool) ->
+        Result<(), EvaluatorError> {
+        match self.signals.get(id) {
+            Some(Some(_)) => {
+                self.control_rate[id] = control_rate;
+                self.unsorted = true;
+                Ok(())
+            },
+            _ => Err(EvaluatorError::UnknownSignal(id))
+        }
+    }
+
+    // Designates the signal whose output is the output of the graph.
+    pub fn set_output(&mut self, id: SignalId) -> Result<(), EvaluatorError> {
+        match self.signals.get(id) {
+            Some(Some(_)) => {
+                self.output = Some(id);
+                self.unsorted = true;
+                Ok(())
+            },
+            _ => Err(EvaluatorError::UnknownSignal(id))
+        }
+    }
+
+    // Connects the specified output channel of the source signal
+    // to an input of the target signal, replacing whatever the input
+    // was previously connected to. Before the next block, the graph is
+    // reordered so that the source is evaluated before the target,
+    // unless the connection closes a cycle (see sort()).
+    pub fn connect(
+        &mut self,
+        source: SignalId,
+        channel: usize,
+        target: SignalId,
+        input: usize,
+        step_size: usize
+    ) -> Result<(), EvaluatorError> {
+        let connection = match self.signals.get(source) {
+            Some(Some(signal)) => match signal.output(channel) {
+                // The Evaluator holds the source signal for the
+                // lifetime 'a, so its output buffer can't move.
+                Some(buffer) => unsafe {
+                    Connection::new(buffer, step_size)
+                },
+                None => return Err(
+                    EvaluatorError::UnknownOutput(source, channel))
+            },
+            _ => return Err(EvaluatorError::UnknownSignal(source))
+        };
+
+        self.replace_input(target, input, connection, Some(source))?;
+
+        Ok(())
+    }
+
+    // Sets the input to a constant value.
+    pub fn disconnect(&mut self, target: SignalId, input: usize, value: f32) ->
+        Result<(), EvaluatorError> {
+        self.replace_input(target, input, Connection::new_constant(value),
+            None)?;
+
+        Ok(())
+    }
+
+    // Changes the value of an input that isn't connected to a signal.
+    // Unlike disconnect(), this leaves the evaluation order alone.
+    pub fn set_input_value(
+        &mut self,
+        target: SignalId,
+        input: usize,
+        value: f32
+    ) -> Result<(), EvaluatorError> {
+        match self.signals.get_mut(target) {
+            Some(Some(signal)) => match signal.input_mut(input) {
+                Some(current) if current.is_constant() => {
+                    current.value = value;
+                    Ok(())
+                },
+                Some(_) => Err(EvaluatorError::InputConnected(target, input)),
+                None => Err(EvaluatorError::UnknownInput(target, input))
+            },
+            _ => Err(EvaluatorError::UnknownSignal(target))
+        }
+    }
+
+    pub fn set_parameter(
+        &mut self,
+        signal: SignalId,
+        parameter: usize,
+        value: f32
+    ) -> Result<(), EvaluatorError> {
+        match self.signals.get_mut(signal) {
+            Some(Some(s)) => match s.parameter_mut(parameter) {
+                Some(current) => {
+                    *current = value;
+                    Ok(())
+                },
+                None => Err(
+                    EvaluatorError::UnknownParameter(signal, parameter))
+            },
+            _ => Err(EvaluatorError::UnknownSignal(signal))
+        }
+    }
+
+    pub fn apply(&mut self, command: Command<'a>) ->
+        Result<Option<Event<'a>>, EvaluatorError> {
+        match command {
+            Command::SetParameter { signal, parameter, value } =>
+                self.set_parameter(signal, parameter, value),
+            Command::Connect { source, channel, target, input, step_size } =>
+                self.connect(source, channel, target, input, step_size),
+            Command::Disconnect { target, input, value } =>
+                self.disconnect(target, input, value),
+            Command::SetInputValue { target, input, value } =>
+                self.set_input_value(target, input, value),
+            Command::AddSignal { id, signal, control_rate } => self
+                .insert(id, signal)
+                .and_then(|_| self.set_control_rate(id, control_rate)),
+            Command::RemoveSignal(id) => return self.remove(id)
+                .map(|signal| Some(Event::Removed(id, signal))),
+            Command::SetOutput(id) => self.set_output(id)
+        }.map(|_| None)
+    }
+
+    // Applies all pending commands. This is intended to be called
+    // on the realtime thread between blocks; it doesn't allocate or block.
+    pub fn drain<F, const N: usize>(
+        &mut self,
+        commands: &mut Consumer<Command<'a>, N>,
+        mut on_event: F
+    ) where F: FnMut(Event<'a>) {
+        while let Some(command) = commands.pop() {
+            match self.apply(command) {
+                Ok(Some(event)) => on_event(event),
+                Ok(None) => (),
+                Err(e) => on_event(Event::Rejected(e))
+            }
+        }
+    }
+
+    // Replaces the connection of one of the target's inputs,
+    // moving its edge from its previous source to the specified one.
+    fn replace_input(
+        &mut self,
+        target: SignalId,
+        input: usize,
+        connection: Connection,
+        source: Option<SignalId>
+    ) -> Result<Connection, EvaluatorError> {
+        let previous = match self.signals.get_mut(target) {
+            Some(Some(signal)) => match signal.input_mut(input) {
+                Some(current) => core::mem::replace(current, connection),
+                None => return Err(
+                    EvaluatorError::UnknownInput(target, input))
+            },
+            _ => return Err(EvaluatorError::UnknownSignal(target))
+        };
+
+        if let Some(previous_source) = self.source_of(&previous) {
+            let edges = &mut self.edges[target][previous_source];
+            debug_assert!(*edges > 0, "Every connection has an edge");
+            *edges = edges.saturating_sub(1);
+        }
+        if let Some(source) = source {
+            let edges = &mut self.edges[target][source];
+            *edges = edges.saturating_add(1);
+        }
+        self.unsorted = true;
+
+        Ok(previous)
+    }
+
+    // Counts the edges of a newly added signal, which may already be
+    // connected to other signals: those of its own inputs, and those
+    // of other signals' inputs that read from its outputs.
+    fn count_edges(&mut self, id: SignalId) {
+        self.edges[id] = [0; MAX_SIGNALS];
+        for target in 0..MAX_SIGNALS {
+            self.edges[target][id] = 0;
+        }
+
+        for target in 0..MAX_SIGNALS {
+            for input in 0.. {
+                let connection = match self.signals[target].as_ref()
+                    .and_then(|signal| signal.input(input)) {
+                    Some(connection) => *connection,
+                    None => break
+                };
+
+                match self.source_of(&connection) {
+                    Some(source) if target == id || source == id => {
+                        let edges = &mut self.edges[target][source];
+                        *edges = edges.saturating_add(1);
+                    },
+                    _ => ()
+                }
+            }
+        }
+    }
+
+    // Finds the signal that owns the output buffer
+    // a Connection is reading from.
+    fn source_of(&self, connection: &Connection) -> Option<SignalId> {
+        self.channel_of(connection).map(|(source, _)| source)
+    }
+
+    // Finds the signal and output channel a Connection is reading from,
+    // either directly or through a Junction.
+    fn channel_of(&self, connection: &Connection) ->
+        Option<(SignalId, usize)> {
+        if connection.is_constant() {
+            return None
+        }
+
+        let junction = self.junctions.iter()
+            .find(|junction| junction.buffer.as_ptr() == connection.buffer);
+        if let Some(junction) = junction {
+            return junction.source
+        }
+
+        self.signals.iter().enumerate().find_map(|(id, slot)| {
+            let signal = slot.as_ref()?;
+            (0..).map_while(|channel| signal.output(channel))
+                .position(|buffer| buffer.as_ptr() == connection.buffer)
+                .map(|channel| (id, channel))
+        })
+    }
+
+    // Orders the signals using Kahn's algorithm. When every remaining
+    // signal is waiting on another, there's a cycle: it's broken by
+    // evaluating the signal with the fewest inputs left waiting next
+    // (the first one added, if there's a tie), so that those inputs
+    // read what their sources generated last time, i.e. one block
+    // earlier, or one sample earlier in a single-sample run.
+    // Ready signals that share the previous signal's block size go first,
+    // so that they're generated together.
+    #[allow(clippy::needless_range_loop)]
+    fn sort(&mut self) {
+        let block_size = self.block_size();
+
+        // The number of inputs each signal has that are connected
+        // to another signal that hasn't been ordered yet.
+        let mut in_degrees = [0_usize; MAX_SIGNALS];
+        for target in 0..MAX_SIGNALS {
+            in_degrees[target] = self.edges[target].iter()
+                .map(|&edges| edges as usize)
+                .sum();
+        }
+
+        let mut ordered = [false; MAX_SIGNALS];
+        let mut order = [0; MAX_SIGNALS];
+        let mut order_len = 0_usize;
+        loop {
+            let remaining = (0..MAX_SIGNALS)
+                .filter(|&id| self.signals[id].is_some() && !ordered[id]);
+            let mut ready = remaining.clone()
+                .filter(|&id| in_degrees[id] == 0);
+            let previous = order_len.checked_sub(1)
+                .map(|n| self.repeats(order[n], block_size));
+
+            let next = ready.clone()
+                .find(|&id| Some(self.repeats(id, block_size)) == previous)
+                .or_else(|| ready.next())
+                .or_else(|| remaining.min_by_key(|&id| in_degrees[id]));
+            let id = match next {
+                Some(id) => id,
+                None => break
+            };
+
+            ordered[id] = true;
+            order[order_len] = id;
+            order_len += 1;
+
+            for target in 0..MAX_SIGNALS {
+                if !ordered[target] {
+                    in_degrees[target] -= self.edges[target][id] as usize;
+                }
+            }
+        }
+
+        self.order = order;
+        self.order_len = order_len;
+        self.unsorted = false;
+        self.plan();
+    }
+
+    // The number of times the signal is generated per block of the graph.
+    fn repeats(&self, id: SignalId, block_size: usize) -> usize {
+        match &self.signals[id] {
+            Some(signal) if !self.control_rate[id] => {
+                let signal_block_size = signal.block_size();
+                if signal_block_size > 0 && signal_block_size < block_size &&
+                    block_size.is_multiple_of(signal_block_size) {
+                    block_size / signal_block_size
+                } else {
+                    1
+                }
+            },
+            _ => 1
+        }
+    }
+
+    // Groups the evaluation order into runs, and points connections
+    // between runs at Junctions and the matching part of the block.
+    fn plan(&mut self) {
+        let block_size = self.block_size();
+
+        self.num_runs = 0;
+        for n in 0..self.order_len {
+            let id = self.order[n];
+            let repeats = self.repeats(id, block_size);
+            match self.num_runs.checked_sub(1) {
+                Some(last) if self.runs[last].repeats == repeats =>
+                    self.runs[last].end = n + 1,
+                _ => {
+                    self.runs[self.num_runs] = Run {
+                        start: n,
+                        end: n + 1,
+                        repeats,
+                        block_size: block_size / repeats
+                    };
+                    self.num_runs += 1;
+                }
+            }
+            self.run_of[id] = self.num_runs - 1;
+        }
+
+        for junction in self.junctions.iter_mut() {
+            junction.used = false;
+        }
+
+        for target in 0..MAX_SIGNALS {
+            self.offset_inputs[target] = 0;
+
+            for input in 0.. {
+                let mut connection = match self.signals[target].as_ref()
+                    .and_then(|signal| signal.input(input)) {
+                    Some(connection) => *connection,
+                    None => break
+                };
+                let (source, channel) = match self.channel_of(&connection) {
+                    Some(source) => source,
+                    None => continue
+                };
+
+                let source_run = self.runs[self.run_of[source]];
+                let target_run = self.runs[self.run_of[target]];
+                let same_run = self.run_of[source] == self.run_of[target];
+
+                let junction = if source_run.repeats > 1 && !same_run {
+                    self.junction_for(source, channel)
+                } else {
+                    None
+                };
+                connection.buffer = match junction {
+                    Some(junction) => self.junctions[junction].buffer.as_ptr(),
+                    None => match self.signals[source].as_ref()
+                        .and_then(|signal| signal.output(channel)) {
+                        Some(buffer) => buffer.as_ptr(),
+                        None => continue
+                    }
+                };
+                connection.offset = 0;
+
+                // Without a Junction, the source's latest output
+                // is all there is to read.
+                let whole_block = source_run.repeats == 1 || junction.is_some();
+                if target_run.repeats > 1 && !same_run && whole_block &&
+                    input < 64 {
+                    self.offset_inputs[target] |= 1 << input;
+                }
+
+                if let Some(current) = self.signals[target].as_mut()
+                    .and_then(|signal| signal.input_mut(input)) {
+                    *current = connection;
+                }
+            }
+        }
+
+        // The graph's output is read after the whole block is generated.
+        if let Some(output) = self.output {
+            if self.runs[self.run_of[output]].repeats > 1 {
+                let num_channels = self.signals[output].as_ref().map_or(0,
+                    |signal| (0..).map_while(|channel| signal.output(channel))
+                        .count());
+                for channel in 0..num_channels {
+                    self.junction_for(output, channel);
+                }
+            }
+        }
+
+        for junction in self.junctions.iter_mut() {
+            if !junction.used {
+                junction.source = None;
+            }
+        }
+    }
+
+    // Finds the Junction that collects the output channel,
+    // or claims a free one.
+    fn junction_for(&mut self, source: SignalId, channel: usize) ->
+        Option<usize> {
+        let index = self.junctions.iter()
+            .position(|junction| junction.source == Some((source, channel)))
+            .or_else(|| self.junctions.iter()
+                .position(|junction| junction.source.is_none()))?;
+
+        let junction = &mut self.junctions[index];
+        junction.source = Some((source, channel));
+        junction.used = true;
+
+        Some(index)
+    }
+
+    // Points the inputs that read from outside of the signal's run
+    // at the specified position in the block.
+    fn offset_inputs(&mut self, id: SignalId, position: usize) {
+        let inputs = self.offset_inputs[id];
+        if let Some(signal) = self.signals[id].as_mut() {
+            for input in (0..64).filter(|input| inputs & (1 << input) != 0) {
+                if let Some(connection) = signal.input_mut(input) {
+                    connection.offset = position * connection.step_size;
+                }
+            }
+        }
+    }
+
+    // Copies the latest output of the run's signals into their Junctions.
+    fn gather(&mut self, run: usize, position: usize, len: usize) {
+        for junction in self.junctions.iter_mut() {
+            let (source, channel) = match junction.source {
+                Some(source) if self.run_of[source.0] == run => source,
+                _ => continue
+            };
+
+            if let Some(buffer) = self.signals[source].as_ref()
+                .and_then(|signal| signal.output(channel)) {
+                junction.buffer[position..position + len]
+                    .copy_from_slice(&buffer[0..len]);
+            }
+        }
+    }
+
+    // Generates one block for every signal in the graph.
+    pub fn evaluate(&mut self) {
+        if self.unsorted {
+            self.sort();
+        }
+
+        for r in 0..self.num_runs {
+            let run = self.runs[r];
+            for step in 0..run.repeats {
+                let position = step * run.block_size;
+                for n in run.start..run.end {
+                    let id = self.order[n];
+                    if run.repeats > 1 {
+                        self.offset_inputs(id, position);
+                    }
+
+                    if let Some(signal) = self.signals[id].as_mut() {
+                        signal.generate();
+                    }
+                }
+
+                if run.repeats > 1 {
+                    self.gather(r, position, run.block_size);
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Graph for Evaluator<'a> {
+    fn evaluate(&mut self) {
+        Evaluator::evaluate(self)
+    }
+
+    fn output(&self, channel: usize) -> Option<&[f32; MAX_BLOCK_SIZE]> {
+        let id = self.output?;
+        let junction = self.junctions.iter()
+            .find(|junction| junction.source == Some((id, channel)));
+
+        match junction {
+            Some(junction) => Some(&junction.buffer),
+            None => self.signal(id).and_then(|signal| signal.output(channel))
+        }
+    }
+}
+
+impl<'a> Default for Evaluator<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::Queue;
+    use crate::signals::*;
+
+    fn settings(num_channels: usize) -> AudioSettings {
+        AudioSettings {
+            sample_rate: 44100.0,
+            block_size: 64,
+            num_channels
+        }
+    }
+
+    #[test]
+    fn value_drives_sine_into_fan() {
+        let mut value = Value::new(settings(1));
+        value.parameters.value = 220.0;
+        let mut sine = Sine::new(settings(1));
+        let mut fan = Fan::new(settings(2));
+
+        // Add the signals in reverse order to ensure
+        // that the evaluator sorts them.
+        let mut evaluator = Evaluator::new();
+        let fan_id = evaluator.add(&mut fan).unwrap();
+        let sine_id = evaluator.add(&mut sine).unwrap();
+        let value_id = evaluator.add(&mut value).unwrap();
+
+        evaluator.connect(value_id, 0, sine_id, 0, 1).unwrap();
+        evaluator.connect(sine_id, 0, fan_id, 0, 1).unwrap();
+
+        evaluator.evaluate();
+
+        let mut expected = Sine::new(settings(1));
+        expected.inputs.freq = Connection::new_constant(220.0);
+        expected.generate();
+
+        let fan_signal = evaluator.signal(fan_id).unwrap();
+        for channel in 0..2 {
+            let actual = fan_signal.output(channel).unwrap();
+            assert_eq!(expected.output.samples[0..64], actual[0..64],
+                "The Fan's channel {} contains the Sine's output", channel);
+        }
+    }
+
+    #[test]
+    fn control_rate_connections_read_the_first_sample() {
+        let mut lfo = Sine::new(settings(1));
+        lfo.inputs.freq = Connection::new_constant(1.0);
+        lfo.inputs.phase_offset = Connection::new_constant(PI / 2.0);
+        lfo.inputs.mul = Connection::new_constant(100.0);
+        lfo.inputs.add = Connection::new_constant(300.0);
+        let mut carrier = Sine::new(settings(1));
+
+        let mut evaluator = Evaluator::new();
+        let carrier_id = evaluator.add(&mut carrier).unwrap();
+        let lfo_id = evaluator.add(&mut lfo).unwrap();
+        evaluator.connect(lfo_id, 0, carrier_id, 0, 0).unwrap();
+
+        evaluator.evaluate();
+
+        let mut expected = Sine::new(settings(1));
+        expected.inputs.freq = Connection::new_constant(400.0);
+        expected.generate();
+
+        let actual = evaluator.signal(carrier_id).unwrap().output(0).unwrap();
+        for (i, (expected, actual)) in expected.output.samples.iter()
+            .zip(actual.iter()).take(64).enumerate() {
+            assert!((expected - actual).abs() < 0.0001,
+                "Sample {} was generated at the LFO's first value", i);
+        }
+    }
+
+    #[test]
+    fn graph_output_is_the_output_signal() {
+        let mut value = Value::new(settings(1));
+        value.parameters.value = 0.25;
+        let mut fan = Fan::new(settings(2));
+
+        let mut evaluator = Evaluator::new();
+        let value_id = evaluator.add(&mut value).unwrap();
+        let fan_id = evaluator.add(&mut fan).unwrap();
+        evaluator.connect(value_id, 0, fan_id, 0, 1).unwrap();
+
+        assert!(Graph::output(&evaluator, 0).is_none(),
+            "A graph without an output signal is silent");
+        assert_eq!(Err(EvaluatorError::UnknownSignal(7)),
+            evaluator.set_output(7));
+
+        evaluator.set_output(fan_id).unwrap();
+        Graph::evaluate(&mut evaluator);
+
+        assert_eq!(0.25, Graph::output(&evaluator, 1).unwrap()[63]);
+        assert!(Graph::output(&evaluator, 2).is_none());
+    }
+
+    #[test]
+    fn cycles_read_the_previous_block() {
+        let mut sine = Sine::new(settings(1));
+        let mut fan = Fan::new(settings(1));
+
+        // The Sine modulates its own phase through the Fan. Both are
+        // waiting on each other, so the first one added is evaluated first.
+        let mut evaluator = Evaluator::new();
+        let sine_id = evaluator.add(&mut sine).unwrap();
+        let fan_id = evaluator.add(&mut fan).unwrap();
+        evaluator.connect(sine_id, 0, fan_id, 0, 1).unwrap();
+        evaluator.connect(fan_id, 0, sine_id, 1, 1).unwrap();
+        evaluator.set_output(fan_id).unwrap();
+
+        let mut expected = Sine::new(settings(1));
+        let mut previous = [0.0; MAX_BLOCK_SIZE];
+        for block in 0..3 {
+            evaluator.evaluate();
+
+            expected.inputs.phase_offset = unsafe {
+                Connection::new(&previous, 1)
+            };
+            expected.generate();
+            previous = expected.output.samples;
+
+            let actual = Graph::output(&evaluator, 0).unwrap();
+            for i in 0..64 {
+                assert!((previous[i] - actual[i]).abs() < 0.0001,
+                    "Block {} was modulated by the one before it", block);
+            }
+        }
+    }
+
+    #[test]
+    fn single_sample_cycles_read_the_previous_sample() {
+        let mut sine = Sine::new(AudioSettings {
+            block_size: 1,
+            ..settings(1)
+        });
+        let mut junctions: [Junction; 1] = [Junction::new()];
+
+        let mut evaluator = Evaluator::with_block_size(64, &mut junctions);
+        let sine_id = evaluator.add(&mut sine).unwrap();
+        evaluator.connect(sine_id, 0, sine_id, 1, 1).unwrap();
+        evaluator.set_output(sine_id).unwrap();
+        evaluator.evaluate();
+
+        let mut expected = Sine::new(AudioSettings {
+            block_size: 1,
+            ..settings(1)
+        });
+        let mut previous = [0.0; MAX_BLOCK_SIZE];
+        for i in 0..64 {
+            expected.inputs.phase_offset =
+                Connection::new_constant(previous[0]);
+            expected.generate();
+            previous = expected.output.samples;
+
+            let actual = Graph::output(&evaluator, 0).unwrap()[i];
+            assert!((previous[0] - actual).abs() < 0.0001,
+                "Sample {} was modulated by the one before it", i);
+        }
+    }
+
+    #[test]
+    fn unknown_inputs_are_rejected() {
+        let mut value = Value::new(settings(1));
+        let mut sine = Sine::new(settings(1));
+
+        let mut evaluator = Evaluator::new();
+        let value_id = evaluator.add(&mut value).unwrap();
+        let sine_id = evaluator.add(&mut sine).unwrap();
+
+        assert_eq!(Err(EvaluatorError::UnknownInput(sine_id, 4)),
+            evaluator.connect(value_id, 0, sine_id, 4, 1));
+        assert_eq!(Err(EvaluatorError::UnknownOutput(value_id, 1)),
+            evaluator.connect(value_id, 1, sine_id, 0, 1));
+        assert_eq!(Err(EvaluatorError::UnknownSignal(42)),
+            evaluator.connect(42, 0, sine_id, 0, 1));
+    }
+
+    #[test]
+    fn commands_are_applied_between_blocks() {
+        let mut value = Value::new(settings(1));
+        let mut fan = Fan::new(settings(2));
+        let mut queue: Queue<Command, 8> = Queue::new();
+        let (mut producer, mut consumer) = queue.split();
+
+        let mut evaluator = Evaluator::new();
+        let fan_id = evaluator.add(&mut fan).unwrap();
+        evaluator.set_output(fan_id).unwrap();
+
+        assert!(producer.push(Command::AddSignal {
+            id: 5,
+            signal: &mut value,
+            control_rate: false
+        }).is_ok());
+        assert!(producer.push(Command::Connect {
+            source: 5, channel: 0, target: fan_id, input: 0, step_size: 1
+        }).is_ok());
+        assert!(producer.push(Command::SetParameter {
+            signal: 5, parameter: 0, value: 0.75
+        }).is_ok());
+
+        let mut events = 0;
+        evaluator.drain(&mut consumer, |_| events += 1);
+        evaluator.evaluate();
+        assert_eq!(0, events, "The commands were all accepted");
+        assert_eq!(0.75, Graph::output(&evaluator, 1).unwrap()[63]);
+
+        assert!(producer.push(Command::SetParameter {
+            signal: 5, parameter: 1, value: 0.0
+        }).is_ok());
+        assert!(producer.push(Command::RemoveSignal(5)).is_ok());
+
+        let mut removed = None;
+        let mut rejected = None;
+        evaluator.drain(&mut consumer, |event| match event {
+            Event::Removed(id, _) => removed = Some(id),
+            Event::Rejected(e) => rejected = Some(e)
+        });
+        assert_eq!(Some(EvaluatorError::UnknownParameter(5, 1)), rejected);
+        assert_eq!(Some(5), removed);
+        assert!(evaluator.signal(5).is_none());
+
+        // The Fan's input was disconnected from the removed Value.
+        evaluator.evaluate();
+        assert_eq!(0.0, Graph::output(&evaluator, 1).unwrap()[63]);
+    }
+
+    #[test]
+    fn disconnected_inputs_are_constant() {
+        let mut value = Value::new(settings(1));
+        value.parameters.value = 0.5;
+        let mut fan = Fan::new(settings(1));
+
+        let mut evaluator = Evaluator::new();
+        let value_id = evaluator.add(&mut value).unwrap();
+        let fan_id = evaluator.add(&mut fan).unwrap();
+        evaluator.connect(value_id, 0, fan_id, 0, 1).unwrap();
+        evaluator.disconnect(fan_id, 0, 0.25).unwrap();
+        evaluator.evaluate();
+
+        assert_eq!(0.25,
+            evaluator.signal(fan_id).unwrap().output(0).unwrap()[0]);
+        assert_eq!(Err(EvaluatorError::SignalExists(value_id)),
+            evaluator.insert(value_id, &mut Value::new(settings(1))));
+    }
+
+    #[test]
+    fn signals_are_ordered_by_their_existing_connections() {
+        let mut value = Value::new(settings(1));
+        value.parameters.value = 0.5;
+        let mut fan = Fan::new(settings(1));
+        fan.inputs.source = unsafe {
+            Connection_new(value.output.samples.as_ptr(), 1)
+        };
+        let mut out = Fan::new(settings(1));
+        out.inputs.source = unsafe {
+            Connection_new(fan.output.channels[0].as_ptr(), 1)
+        };
+
+        // Add the signals in reverse order, so that the Fans
+        // are added before the signals they read from.
+        let mut evaluator = Evaluator::new();
+        let out_id = evaluator.add(&mut out).unwrap();
+        let fan_id = evaluator.add(&mut fan).unwrap();
+        let value_id = evaluator.add(&mut value).unwrap();
+        evaluator.evaluate();
+
+        assert_eq!(0.5,
+            evaluator.signal(out_id).unwrap().output(0).unwrap()[0]);
+
+        // Signals that are removed and added again keep their connections.
+        let fan = evaluator.remove(fan_id).unwrap();
+        evaluator.insert(fan_id, fan).unwrap();
+        evaluator.disconnect(fan_id, 0, 0.25).unwrap();
+        evaluator.connect(value_id, 0, fan_id, 0, 1).unwrap();
+        evaluator.evaluate();
+
+        assert_eq!(0.5,
+            evaluator.signal(fan_id).unwrap().output(0).unwrap()[0]);
+    }
+
+    #[test]
+    fn replaced_connections_no_longer_order_the_graph() {
+        let mut first = Fan::new(settings(1));
+        let mut second = Fan::new(settings(1));
+
+        let mut evaluator = Evaluator::new();
+        let first_id = evaluator.add(&mut first).unwrap();
+        let second_id = evaluator.add(&mut second).unwrap();
+        evaluator.connect(first_id, 0, second_id, 0, 1).unwrap();
+        evaluator.disconnect(second_id, 0, 0.25).unwrap();
+        evaluator.connect(second_id, 0, first_id, 0, 1).unwrap();
+        evaluator.evaluate();
+
+        assert_eq!(0.25,
+            evaluator.signal(first_id).unwrap().output(0).unwrap()[0],
+            "The first signal is evaluated after the second");
+    }
+
+    #[test]
+    fn only_constant_inputs_can_be_set() {
+        let mut value = Value::new(settings(1));
+        value.parameters.value = 0.5;
+        let mut fan = Fan::new(settings(1));
+
+        let mut evaluator = Evaluator::new();
+        let value_id = evaluator.add(&mut value).unwrap();
+        let fan_id = evaluator.add(&mut fan).unwrap();
+        evaluator.set_input_value(fan_id, 0, 0.25).unwrap();
+        evaluator.evaluate();
+        assert_eq!(0.25,
+            evaluator.signal(fan_id).unwrap().output(0).unwrap()[0]);
+
+        evaluator.connect(value_id, 0, fan_id, 0, 1).unwrap();
+        assert_eq!(Err(EvaluatorError::InputConnected(fan_id, 0)),
+            evaluator.set_input_value(fan_id, 0, 0.75));
+        evaluator.evaluate();
+        assert_eq!(0.5,
+            evaluator.signal(fan_id).unwrap().output(0).unwrap()[0],
+            "The connection was left alone");
+    }
+
+    fn sized(num_channels: usize, block_size: usize) -> AudioSettings {
+        AudioSettings {
+            block_size,
+            ..settings(num_channels)
+        }
+    }
+
+    fn assert_close(expected: &[f32], actual: &[f32], message: &str) {
+        for (i, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+            assert!((expected - actual).abs() < 0.0001,
+                "{}: sample {} was {}, not {}", message, i, actual, expected);
+        }
+    }
+
+    #[test]
+    fn smaller_blocks_are_generated_several_times_per_block() {
+        // The carrier reads from a larger block and is read by one.
+        let mut modulator = Sine::new(sized(1, 8));
+        modulator.inputs.freq = Connection::new_constant(1000.0);
+        modulator.inputs.mul = Connection::new_constant(100.0);
+        modulator.inputs.add = Connection::new_constant(440.0);
+        let mut carrier = Sine::new(sized(1, 2));
+        let mut fan = Fan::new(sized(2, 8));
+        let mut junctions: [Junction; 2] = core::array::from_fn(|_|
+            Junction::new());
+
+        let mut evaluator = Evaluator::with_block_size(8, &mut junctions);
+        let modulator_id = evaluator.add(&mut modulator).unwrap();
+        let carrier_id = evaluator.add(&mut carrier).unwrap();
+        let fan_id = evaluator.add(&mut fan).unwrap();
+        evaluator.connect(modulator_id, 0, carrier_id, 0, 1).unwrap();
+        evaluator.connect(carrier_id, 0, fan_id, 0, 1).unwrap();
+        evaluator.set_output(fan_id).unwrap();
+
+        let mut expected_modulator = Sine::new(sized(1, 16));
+        expected_modulator.inputs = SineInputs {
+            freq: Connection::new_constant(1000.0),
+            phase_offset: Connection::new_constant(0.0),
+            mul: Connection::new_constant(100.0),
+            add: Connection::new_constant(440.0)
+        };
+        expected_modulator.generate();
+        let mut expected = Sine::new(sized(1, 16));
+        expected.inputs.freq = unsafe {
+            Connection::new(&expected_modulator.output.samples, 1)
+        };
+        expected.generate();
+
+        evaluator.evaluate();
+        assert_close(&expected.output.samples[0..8],
+            &Graph::output(&evaluator, 1).unwrap()[0..8],
+            "The first block");
+        evaluator.evaluate();
+        assert_close(&expected.output.samples[8..16],
+            &Graph::output(&evaluator, 1).unwrap()[0..8],
+            "The second block");
+    }
+
+    #[test]
+    fn single_sample_runs_are_generated_sample_by_sample() {
+        let mut level = Value::new(sized(1, 1));
+        level.parameters.value = 0.5;
+        let mut sine = Sine::new(sized(1, 1));
+        let mut fan = Fan::new(sized(1, 1));
+        let mut junctions: [Junction; 1] = [Junction::new()];
+
+        let mut evaluator = Evaluator::with_block_size(4, &mut junctions);
+        let fan_id = evaluator.add(&mut fan).unwrap();
+        let sine_id = evaluator.add(&mut sine).unwrap();
+        let level_id = evaluator.add(&mut level).unwrap();
+        evaluator.connect(level_id, 0, sine_id, 2, 1).unwrap();
+        evaluator.connect(sine_id, 0, fan_id, 0, 1).unwrap();
+        evaluator.set_output(fan_id).unwrap();
+        evaluator.evaluate();
+
+        let mut expected = Sine::new(sized(1, 4));
+        expected.inputs.mul = Connection::new_constant(0.5);
+        expected.generate();
+
+        assert_close(&expected.output.samples[0..4],
+            &Graph::output(&evaluator, 0).unwrap()[0..4],
+            "The output is collected from every sample");
+    }
+
+    #[test]
+    fn control_rate_signals_are_generated_once_per_block() {
+        // Control-rate signals run at the block rate.
+        let sample_rate = settings(1).sample_rate;
+        let mut lfo = Sine::new(AudioSettings {
+            sample_rate: sample_rate / 4.0,
+            ..sized(1, 1)
+        });
+        lfo.inputs.freq = Connection::new_constant(1000.0);
+        let mut junctions: [Junction; 1] = [Junction::new()];
+
+        let mut evaluator = Evaluator::with_block_size(4, &mut junctions);
+        let lfo_id = evaluator.add(&mut lfo).unwrap();
+        evaluator.set_control_rate(lfo_id, true).unwrap();
+        evaluator.set_output(lfo_id).unwrap();
+        evaluator.evaluate();
+        evaluator.evaluate();
+
+        let phase = TWO_PI * 1000.0 * 4.0 / sample_rate;
+        assert_close(&[libm::sinf(phase)],
+            &Graph::output(&evaluator, 0).unwrap()[0..1],
+            "The LFO's phase advanced by a block's worth");
+        assert_eq!(Err(EvaluatorError::UnknownSignal(3)),
+            evaluator.set_control_rate(3, true));
+    }
+
+    #[test]
+    fn released_junctions_are_no_longer_read() {
+        let mut sine = Sine::new(sized(1, 2));
+        let mut fan = Fan::new(sized(1, 4));
+        let mut junctions: [Junction; 1] = [Junction::new()];
+
+        let mut evaluator = Evaluator::with_block_size(4, &mut junctions);
+        let sine_id = evaluator.add(&mut sine).unwrap();
+        let fan_id = evaluator.add(&mut fan).unwrap();
+        evaluator.connect(sine_id, 0, fan_id, 0, 1).unwrap();
+        evaluator.evaluate();
+
+        let junctions = evaluator.release_junctions();
+        let connection = *evaluator.signal(fan_id).unwrap().input(0).unwrap();
+        assert_eq!(evaluator.signal(sine_id).unwrap().output(0).unwrap()
+            .as_ptr(), connection.buffer);
+        assert!(junctions[0].buffer[3] != 0.0,
+            "The Junction collected the Sine's second block");
+        evaluator.evaluate();
+    }
+}
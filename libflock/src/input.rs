@@ -0,0 +1,220 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::queue::Queue;
+use crate::signals::{AudioSettings, MultichannelBuffer, MAX_CHANNEL_COUNT};
+
+pub type Frame = [f32; MAX_CHANNEL_COUNT];
+
+// The number of frames that can be buffered between
+// an input device and the graph.
+#[cfg(feature = "lowmem")]
+pub const INPUT_QUEUE_SIZE: usize = 512;
+#[cfg(not(feature = "lowmem"))]
+pub const INPUT_QUEUE_SIZE: usize = 4096;
+
+// Carries frames from an input device's callback to the graph,
+// which reads them one block at a time for AudioIn signals.
+//
+// The input and output devices may have separate clocks, so they drift
+// apart over time. Before reading, the AudioInput buffers enough frames
+// to cover the latency between the two devices' callbacks. If the input
+// falls behind and the queue runs dry, the rest of the block is silent
+// and the AudioInput buffers again; if the input gets ahead, the oldest
+// frames are skipped so that the latency doesn't keep growing.
+pub struct AudioInput {
+    settings: AudioSettings,
+    frames: Queue<Frame, INPUT_QUEUE_SIZE>,
+    // The most recently read block. Only the reading thread accesses it.
+    block: UnsafeCell<MultichannelBuffer>,
+    latency: AtomicUsize,
+    buffering: AtomicBool,
+    underruns: AtomicUsize,
+    dropped_frames: AtomicUsize
+}
+
+// The block is only ever accessed by the reading thread,
+// and the queue synchronizes its own producer and consumer.
+unsafe impl Sync for AudioInput {}
+
+impl AudioInput {
+    pub fn new(settings: AudioSettings) -> AudioInput {
+        AudioInput {
+            settings,
+            frames: Queue::new(),
+            block: UnsafeCell::new(MultichannelBuffer::new_silent()),
+            latency: AtomicUsize::new(settings.block_size * 2),
+            buffering: AtomicBool::new(true),
+            underruns: AtomicUsize::new(0),
+            dropped_frames: AtomicUsize::new(0)
+        }
+    }
+
+    pub fn latency(&self) -> usize {
+        self.latency.load(Ordering::Relaxed)
+    }
+
+    // Sets the number of frames to buffer before reading,
+    // which should cover both devices' buffer sizes.
+    pub fn set_latency(&self, frames: usize) {
+        let max_latency = (INPUT_QUEUE_SIZE - 1) / 2;
+        self.latency.store(frames.clamp(self.settings.block_size,
+            max_latency), Ordering::Relaxed);
+    }
+
+    // The number of times the queue has run dry.
+    pub fn underruns(&self) -> usize {
+        self.underruns.load(Ordering::Relaxed)
+    }
+
+    // The number of frames that were lost
+    // because the input got too far ahead.
+    pub fn dropped_frames(&self) -> usize {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
+
+    // The block that AudioIn signals read from.
+    pub fn block(&self) -> *const MultichannelBuffer {
+        self.block.get()
+    }
+
+    /// # Safety
+    /// Only one thread may write frames at a time,
+    /// e.g. the input device's callback.
+    pub unsafe fn write(&self, samples: &[f32]) {
+        let mut frame = [0.0; MAX_CHANNEL_COUNT];
+        for (sample, value) in frame.iter_mut().zip(samples) {
+            *sample = *value;
+        }
+
+        if self.frames.producer().push(frame).is_err() {
+            self.dropped_frames.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// # Safety
+    /// Only one thread may read blocks at a time, and not while
+    /// the AudioIn signals reading from this input are generating.
+    pub unsafe fn read(&self) {
+        let block = &mut *self.block.get();
+        let block_size = self.settings.block_size;
+        let latency = self.latency();
+        let mut frames = self.frames.consumer();
+        let available = self.frames.len();
+
+        if self.buffering.load(Ordering::Relaxed) {
+            if available < latency {
+                silence(block, 0, block_size);
+                return
+            }
+            self.buffering.store(false, Ordering::Relaxed);
+        }
+
+        if available > latency * 2 {
+            let skipped = available - latency;
+            for _ in 0..skipped {
+                frames.pop();
+            }
+            self.dropped_frames.fetch_add(skipped, Ordering::Relaxed);
+        }
+
+        for i in 0..block_size {
+            match frames.pop() {
+                Some(frame) => {
+                    for (channel, sample) in block.channels.iter_mut()
+                        .zip(frame) {
+                        channel[i] = sample;
+                    }
+                },
+                None => {
+                    silence(block, i, block_size);
+                    self.underruns.fetch_add(1, Ordering::Relaxed);
+                    self.buffering.store(true, Ordering::Relaxed);
+                    return
+                }
+            }
+        }
+    }
+}
+
+fn silence(block: &mut MultichannelBuffer, start: usize, end: usize) {
+    for channel in block.channels.iter_mut() {
+        channel[start..end].fill(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> AudioSettings {
+        AudioSettings {
+            sample_rate: 44100.0,
+            block_size: 4,
+            num_channels: 2
+        }
+    }
+
+    fn write_frames(input: &AudioInput, values: core::ops::Range<usize>) {
+        for value in values {
+            unsafe {
+                input.write(&[value as f32, -(value as f32)]);
+            }
+        }
+    }
+
+    fn block(input: &AudioInput) -> &MultichannelBuffer {
+        unsafe {
+            &*input.block()
+        }
+    }
+
+    #[test]
+    fn frames_are_read_after_buffering() {
+        let input = AudioInput::new(settings());
+        assert_eq!(8, input.latency());
+
+        write_frames(&input, 1..5);
+        unsafe { input.read() };
+        assert_eq!([0.0; 4], block(&input).channels[0][0..4],
+            "Nothing is read until the latency has been buffered");
+
+        write_frames(&input, 5..9);
+        unsafe { input.read() };
+        assert_eq!([1.0, 2.0, 3.0, 4.0], block(&input).channels[0][0..4]);
+        assert_eq!([-1.0, -2.0, -3.0, -4.0],
+            block(&input).channels[1][0..4]);
+        if MAX_CHANNEL_COUNT > 2 {
+            assert_eq!(0.0, block(&input).channels[MAX_CHANNEL_COUNT - 1][0],
+                "Channels the device doesn't have are silent");
+        }
+    }
+
+    #[test]
+    fn underruns_are_silent_until_buffered_again() {
+        let input = AudioInput::new(settings());
+        write_frames(&input, 1..11);
+
+        unsafe { input.read() };
+        unsafe { input.read() };
+        unsafe { input.read() };
+        assert_eq!([9.0, 10.0, 0.0, 0.0], block(&input).channels[0][0..4]);
+        assert_eq!(1, input.underruns());
+
+        write_frames(&input, 11..15);
+        unsafe { input.read() };
+        assert_eq!([0.0; 4], block(&input).channels[0][0..4]);
+        write_frames(&input, 15..19);
+        unsafe { input.read() };
+        assert_eq!([11.0, 12.0, 13.0, 14.0], block(&input).channels[0][0..4]);
+    }
+
+    #[test]
+    fn frames_are_skipped_when_the_input_gets_ahead() {
+        let input = AudioInput::new(settings());
+        write_frames(&input, 1..21);
+
+        unsafe { input.read() };
+        assert_eq!(12, input.dropped_frames());
+        assert_eq!([13.0, 14.0, 15.0, 16.0], block(&input).channels[0][0..4]);
+    }
+}
@@ -2,4 +2,7 @@
 // whenever the crate is published.
 #![no_std]
 
+pub mod evaluator;
+pub mod input;
+pub mod queue;
 pub mod signals;
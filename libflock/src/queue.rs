@@ -0,0 +1,210 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// A fixed-capacity, lock-free, single-producer/single-consumer queue,
+// used to send messages to and from the realtime thread without
+// allocating or locking. A Queue with N slots holds up to N - 1 items.
+//
+// The Queue is split into a Producer and a Consumer,
+// each of which can be handed to a different thread.
+pub struct Queue<T, const N: usize> {
+    buffer: [UnsafeCell<MaybeUninit<T>>; N],
+    // The index of the next item to be read. Only the Consumer writes it.
+    head: AtomicUsize,
+    // The index of the next slot to be written. Only the Producer writes it.
+    tail: AtomicUsize
+}
+
+// Items are only ever accessed by one of the Producer or Consumer,
+// which synchronize their ownership through head and tail.
+unsafe impl<T: Send, const N: usize> Sync for Queue<T, N> {}
+
+impl<T, const N: usize> Queue<T, N> {
+    pub fn new() -> Queue<T, N> {
+        Queue {
+            buffer: core::array::from_fn(|_|
+                UnsafeCell::new(MaybeUninit::uninit())),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0)
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        N.saturating_sub(1)
+    }
+
+    pub fn len(&self) -> usize {
+        if N == 0 {
+            return 0
+        }
+
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+
+        (tail + N - head) % N
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn split(&mut self) -> (Producer<'_, T, N>, Consumer<'_, T, N>) {
+        (Producer { queue: self }, Consumer { queue: self })
+    }
+
+    /// # Safety
+    /// There must never be more than one Producer for the queue at a time,
+    /// including those borrowed from a shared queue, e.g. one in an Arc.
+    pub unsafe fn producer(&self) -> Producer<'_, T, N> {
+        Producer { queue: self }
+    }
+
+    /// # Safety
+    /// There must never be more than one Consumer for the queue at a time,
+    /// including those borrowed from a shared queue.
+    pub unsafe fn consumer(&self) -> Consumer<'_, T, N> {
+        Consumer { queue: self }
+    }
+
+    fn push(&self, item: T) -> Result<(), T> {
+        if N == 0 {
+            return Err(item)
+        }
+
+        let tail = self.tail.load(Ordering::Relaxed);
+        let next = (tail + 1) % N;
+        if next == self.head.load(Ordering::Acquire) {
+            return Err(item)
+        }
+
+        // The Consumer won't read this slot until tail has advanced past it.
+        unsafe {
+            (*self.buffer[tail].get()).write(item);
+        }
+        self.tail.store(next, Ordering::Release);
+
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        if N == 0 || head == self.tail.load(Ordering::Acquire) {
+            return None
+        }
+
+        // The Producer won't write this slot until head has advanced past it.
+        let item = unsafe {
+            (*self.buffer[head].get()).assume_init_read()
+        };
+        self.head.store((head + 1) % N, Ordering::Release);
+
+        Some(item)
+    }
+}
+
+impl<T, const N: usize> Default for Queue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for Queue<T, N> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+pub struct Producer<'a, T, const N: usize> {
+    queue: &'a Queue<T, N>
+}
+
+// The Producer is the only writer of the queue's tail.
+unsafe impl<'a, T: Send, const N: usize> Send for Producer<'a, T, N> {}
+
+impl<'a, T, const N: usize> Producer<'a, T, N> {
+    // Enqueues the item, or hands it back if the queue is full.
+    pub fn push(&mut self, item: T) -> Result<(), T> {
+        self.queue.push(item)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.queue.len() == self.queue.capacity()
+    }
+}
+
+pub struct Consumer<'a, T, const N: usize> {
+    queue: &'a Queue<T, N>
+}
+
+// The Consumer is the only writer of the queue's head.
+unsafe impl<'a, T: Send, const N: usize> Send for Consumer<'a, T, N> {}
+
+impl<'a, T, const N: usize> Consumer<'a, T, N> {
+    pub fn pop(&mut self) -> Option<T> {
+        self.queue.pop()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+
+    #[test]
+    fn items_are_received_in_order() {
+        let mut queue: Queue<u32, 4> = Queue::new();
+        assert_eq!(3, queue.capacity());
+
+        let (mut producer, mut consumer) = queue.split();
+        assert_eq!(None, consumer.pop());
+
+        for item in 0..3 {
+            producer.push(item).unwrap();
+        }
+        assert!(producer.is_full());
+        assert_eq!(Err(3), producer.push(3), "A full queue rejects items");
+
+        assert_eq!(Some(0), consumer.pop());
+        producer.push(3).unwrap();
+        for item in 1..4 {
+            assert_eq!(Some(item), consumer.pop());
+        }
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn items_cross_threads() {
+        // Leak the queue so that both threads can borrow it for 'static.
+        let queue = std::boxed::Box::leak(std::boxed::Box::new(
+            Queue::<usize, 8>::new()));
+        let (mut producer, mut consumer) = queue.split();
+
+        let sender = std::thread::spawn(move || {
+            for item in 0..1000 {
+                let mut pending = item;
+                while let Err(rejected) = producer.push(pending) {
+                    pending = rejected;
+                    std::thread::yield_now();
+                }
+            }
+        });
+
+        let mut expected = 0;
+        while expected < 1000 {
+            match consumer.pop() {
+                Some(item) => {
+                    assert_eq!(expected, item);
+                    expected += 1;
+                },
+                None => std::thread::yield_now()
+            }
+        }
+
+        sender.join().unwrap();
+    }
+}
@@ -1,3 +1,4 @@
+use core::convert::TryFrom;
 use libm;
 
 // Bindgen won't allow the reference to core::f32::consts.
@@ -21,6 +22,7 @@ pub const MAX_CHANNEL_COUNT: usize = 8;
 // for a public API. Consider having a more sensible
 // public API and then transforming to a new struct
 // with runtime-appropriate types.
+#[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct AudioSettings {
     pub sample_rate: f32,
@@ -84,14 +86,117 @@ pub extern "C" fn MultichannelBuffer_new_silent() -> MultichannelBuffer {
     MultichannelBuffer::new_silent()
 }
 
-pub trait Signal {
+// Signals are Send so that a graph can be built on one thread
+// and then handed off to the audio thread.
+pub trait Signal: Send {
     fn generate(&mut self);
+
+    // The number of samples generated by each call to generate().
+    fn block_size(&self) -> usize;
+
+    // Inputs and outputs are addressed by index so that an Evaluator
+    // can wire signals together without knowing their concrete types.
+    fn input(&self, _index: usize) -> Option<&Connection> {
+        None
+    }
+
+    fn input_mut(&mut self, _index: usize) -> Option<&mut Connection> {
+        None
+    }
+
+    // Parameters are values that aren't signal inputs,
+    // such as a Value's value.
+    fn parameter_mut(&mut self, _index: usize) -> Option<&mut f32> {
+        None
+    }
+
+    fn output(&self, channel: usize) -> Option<&[f32; MAX_BLOCK_SIZE]>;
 }
 
+// A Connection reads samples either from another signal's output buffer
+// or, when the buffer is null, from a constant value.
+// The step size determines how far the read position advances for each
+// sample that is generated; audio-rate inputs have a step size of 1, and
+// control-rate inputs have a step size of 0 (i.e. only the first sample
+// of the block is read).
+// The offset is the position that reading starts from, which an Evaluator
+// advances when a signal generates several smaller blocks per block.
+#[derive(Debug, Clone, Copy)]
 #[repr(C)]
-pub struct Connection<'a> {
-    pub buffer: &'a MonoBuffer,
-    pub step_size: usize
+pub struct Connection {
+    pub(crate) buffer: *const f32,
+    pub value: f32,
+    pub step_size: usize,
+    pub offset: usize
+}
+
+// Connections are moved to the audio thread along with
+// the signals that own them and the buffers they point to.
+unsafe impl Send for Connection {}
+
+impl Connection {
+    pub fn new_constant(value: f32) -> Connection {
+        Connection {
+            buffer: core::ptr::null(),
+            value,
+            step_size: 0,
+            offset: 0
+        }
+    }
+
+    /// # Safety
+    /// The buffer must not be moved, dropped or written to
+    /// while this Connection is still being read from.
+    pub unsafe fn new(buffer: &[f32; MAX_BLOCK_SIZE],
+        step_size: usize) -> Connection {
+        Connection {
+            buffer: buffer.as_ptr(),
+            value: 0.0,
+            step_size,
+            offset: 0
+        }
+    }
+
+    pub fn is_constant(&self) -> bool {
+        self.buffer.is_null()
+    }
+
+    // Only signals read from Connections, while the Evaluator
+    // that owns them guarantees that their buffers are alive.
+    #[inline(always)]
+    pub(crate) fn read(&self, i: usize) -> f32 {
+        if self.buffer.is_null() {
+            return self.value
+        }
+
+        // Clamp the read position to the end of the buffer
+        // so that large step sizes can't read past it.
+        let position = core::cmp::min(self.offset + i * self.step_size,
+            MAX_BLOCK_SIZE - 1);
+
+        unsafe {
+            *self.buffer.add(position)
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn Connection_new_constant(value: f32) -> Connection {
+    Connection::new_constant(value)
+}
+
+/// # Safety
+/// The buffer must point to MAX_BLOCK_SIZE samples, which must not be
+/// moved, freed or written to while this Connection is being read from.
+#[no_mangle]
+pub unsafe extern "C" fn Connection_new(buffer: *const f32,
+    step_size: usize) -> Connection {
+    Connection {
+        buffer,
+        value: 0.0,
+        step_size,
+        offset: 0
+    }
 }
 
 #[repr(C)]
@@ -132,6 +237,26 @@ impl Signal for Value {
         for i in 0..self.settings.block_size {
             self.output.samples[i] = self.parameters.value;
         }
+
+        self.last_sample = self.parameters.value;
+    }
+
+    fn block_size(&self) -> usize {
+        self.settings.block_size
+    }
+
+    fn parameter_mut(&mut self, index: usize) -> Option<&mut f32> {
+        match index {
+            0 => Some(&mut self.parameters.value),
+            _ => None
+        }
+    }
+
+    fn output(&self, channel: usize) -> Option<&[f32; MAX_BLOCK_SIZE]> {
+        match channel {
+            0 => Some(&self.output.samples),
+            _ => None
+        }
     }
 }
 
@@ -140,111 +265,1668 @@ pub extern "C" fn Value_new(settings: AudioSettings) -> Value {
     Value::new(settings)
 }
 
-#[no_mangle]
-pub extern "C" fn Value_generate(value: &mut Value) {
-    value.generate()
+#[no_mangle]
+pub extern "C" fn Value_generate(value: &mut Value) {
+    value.generate()
+}
+
+#[repr(C)]
+pub struct SineInputs {
+    pub freq: Connection,
+    pub phase_offset: Connection,
+    pub mul: Connection,
+    pub add: Connection
+}
+
+#[repr(C)]
+pub struct Sine {
+    pub settings: AudioSettings,
+    pub inputs: SineInputs,
+    pub output: MonoBuffer,
+    pub phase_accumulator: f32
+}
+
+impl Sine {
+    pub fn new(settings: AudioSettings) -> Sine {
+        Sine {
+            settings,
+
+            // TODO: Implement default merging.
+            inputs: SineInputs {
+                freq: Connection::new_constant(440.0),
+                phase_offset: Connection::new_constant(0.0),
+                mul: Connection::new_constant(1.0),
+                add: Connection::new_constant(0.0)
+            },
+            output: MonoBuffer::new_silent(),
+            phase_accumulator: 0.0
+        }
+    }
+}
+
+impl Signal for Sine {
+    fn generate(&mut self) {
+        // TODO: Write a macro to handle to the core loop boilerplate
+        // and scaling/offset logic.
+
+        for i in 0..self.settings.block_size {
+            // TODO: Do negative values need to be handled?
+            let modulated_phase = (self.phase_accumulator +
+                self.inputs.phase_offset.read(i)) % TWO_PI;
+
+            self.output.samples[i] = libm::sinf(modulated_phase) *
+                self.inputs.mul.read(i) +
+                self.inputs.add.read(i);
+
+            let phase_step = self.inputs.freq.read(i) /
+                self.settings.sample_rate * TWO_PI;
+
+            self.phase_accumulator += phase_step;
+            if self.phase_accumulator > TWO_PI {
+                self.phase_accumulator -= TWO_PI;
+            }
+        }
+    }
+
+    fn block_size(&self) -> usize {
+        self.settings.block_size
+    }
+
+    fn input(&self, index: usize) -> Option<&Connection> {
+        match index {
+            0 => Some(&self.inputs.freq),
+            1 => Some(&self.inputs.phase_offset),
+            2 => Some(&self.inputs.mul),
+            3 => Some(&self.inputs.add),
+            _ => None
+        }
+    }
+
+    fn input_mut(&mut self, index: usize) -> Option<&mut Connection> {
+        match index {
+            0 => Some(&mut self.inputs.freq),
+            1 => Some(&mut self.inputs.phase_offset),
+            2 => Some(&mut self.inputs.mul),
+            3 => Some(&mut self.inputs.add),
+            _ => None
+        }
+    }
+
+    fn output(&self, channel: usize) -> Option<&[f32; MAX_BLOCK_SIZE]> {
+        match channel {
+            0 => Some(&self.output.samples),
+            _ => None
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn Sine_new(settings: AudioSettings) -> Sine {
+    Sine::new(settings)
+}
+
+#[no_mangle]
+pub extern "C" fn Sine_generate(sine: &mut Sine) {
+    sine.generate()
+}
+
+// The inputs of the band-limited oscillators,
+// laid out the same way as a Sine's.
+#[repr(C)]
+pub struct OscillatorInputs {
+    pub freq: Connection,
+    pub phase_offset: Connection,
+    pub mul: Connection,
+    pub add: Connection
+}
+
+impl OscillatorInputs {
+    pub fn new() -> OscillatorInputs {
+        OscillatorInputs {
+            freq: Connection::new_constant(440.0),
+            phase_offset: Connection::new_constant(0.0),
+            mul: Connection::new_constant(1.0),
+            add: Connection::new_constant(0.0)
+        }
+    }
+}
+
+impl Default for OscillatorInputs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn wrap(phase: f32) -> f32 {
+    phase - libm::floorf(phase)
+}
+
+// The PolyBLEP residual for a step of 2 at the start of the cycle,
+// where the phase and its increment are measured in cycles.
+fn poly_blep(phase: f32, increment: f32) -> f32 {
+    if phase < increment {
+        let x = phase / increment;
+        2.0 * x - x * x - 1.0
+    } else if phase > 1.0 - increment {
+        let x = (phase - 1.0) / increment;
+        x * x + 2.0 * x + 1.0
+    } else {
+        0.0
+    }
+}
+
+// The PolyBLAMP residual for a change in slope
+// of 1 per sample at the start of the cycle.
+fn poly_blamp(phase: f32, increment: f32) -> f32 {
+    if phase < increment {
+        let x = 1.0 - phase / increment;
+        x * x * x / 6.0
+    } else if phase > 1.0 - increment {
+        let x = (phase - 1.0) / increment + 1.0;
+        x * x * x / 6.0
+    } else {
+        0.0
+    }
+}
+
+// Generates a block of a waveform, which is given the sample index,
+// the phase and the phase increment, both measured in cycles.
+// Like a Sine's, the phase offset is measured in radians.
+#[allow(clippy::too_many_arguments)]
+fn oscillate<F>(
+    settings: &AudioSettings,
+    freq: &Connection,
+    phase_offset: &Connection,
+    mul: &Connection,
+    add: &Connection,
+    phase: &mut f32,
+    output: &mut MonoBuffer,
+    waveform: F
+) where F: Fn(usize, f32, f32) -> f32 {
+    for i in 0..settings.block_size {
+        let increment = freq.read(i) / settings.sample_rate;
+        let modulated_phase = wrap(*phase + phase_offset.read(i) / TWO_PI);
+
+        // Edges more than half a cycle wide would overlap.
+        let edge_width = libm::fabsf(increment).min(0.5);
+        output.samples[i] = waveform(i, modulated_phase, edge_width) *
+            mul.read(i) + add.read(i);
+
+        *phase = wrap(*phase + increment);
+    }
+}
+
+fn pulse(phase: f32, increment: f32, width: f32) -> f32 {
+    let width = width.clamp(0.0, 1.0);
+    let naive = if phase < width { 1.0 } else { -1.0 };
+
+    naive + poly_blep(phase, increment) -
+        poly_blep(wrap(phase - width), increment)
+}
+
+// Implements Signal for oscillators with OscillatorInputs,
+// whose waveform is a function of the phase and its increment.
+macro_rules! oscillator_signal {
+    ($name:ident, $waveform:expr) => {
+        impl Signal for $name {
+            fn generate(&mut self) {
+                oscillate(&self.settings, &self.inputs.freq,
+                    &self.inputs.phase_offset, &self.inputs.mul,
+                    &self.inputs.add, &mut self.phase_accumulator,
+                    &mut self.output, |_, phase, increment|
+                        $waveform(phase, increment));
+            }
+
+            fn block_size(&self) -> usize {
+                self.settings.block_size
+            }
+
+            fn input(&self, index: usize) -> Option<&Connection> {
+                match index {
+                    0 => Some(&self.inputs.freq),
+                    1 => Some(&self.inputs.phase_offset),
+                    2 => Some(&self.inputs.mul),
+                    3 => Some(&self.inputs.add),
+                    _ => None
+                }
+            }
+
+            fn input_mut(&mut self, index: usize) ->
+                Option<&mut Connection> {
+                match index {
+                    0 => Some(&mut self.inputs.freq),
+                    1 => Some(&mut self.inputs.phase_offset),
+                    2 => Some(&mut self.inputs.mul),
+                    3 => Some(&mut self.inputs.add),
+                    _ => None
+                }
+            }
+
+            fn output(&self, channel: usize) ->
+                Option<&[f32; MAX_BLOCK_SIZE]> {
+                match channel {
+                    0 => Some(&self.output.samples),
+                    _ => None
+                }
+            }
+        }
+    }
+}
+
+// A band-limited sawtooth, which rises through zero
+// at the start of the cycle like a Sine.
+// The phase accumulator is measured in cycles.
+#[repr(C)]
+pub struct Saw {
+    pub settings: AudioSettings,
+    pub inputs: OscillatorInputs,
+    pub output: MonoBuffer,
+    pub phase_accumulator: f32
+}
+
+impl Saw {
+    pub fn new(settings: AudioSettings) -> Saw {
+        Saw {
+            settings,
+            inputs: OscillatorInputs::new(),
+            output: MonoBuffer::new_silent(),
+            phase_accumulator: 0.0
+        }
+    }
+}
+
+oscillator_signal!(Saw, |phase: f32, increment: f32| {
+    let shifted = wrap(phase + 0.5);
+    2.0 * shifted - 1.0 - poly_blep(shifted, increment)
+});
+
+#[no_mangle]
+pub extern "C" fn Saw_new(settings: AudioSettings) -> Saw {
+    Saw::new(settings)
+}
+
+#[no_mangle]
+pub extern "C" fn Saw_generate(saw: &mut Saw) {
+    saw.generate()
+}
+
+// A band-limited square wave, which is high for the first half of the cycle.
+#[repr(C)]
+pub struct Square {
+    pub settings: AudioSettings,
+    pub inputs: OscillatorInputs,
+    pub output: MonoBuffer,
+    pub phase_accumulator: f32
+}
+
+impl Square {
+    pub fn new(settings: AudioSettings) -> Square {
+        Square {
+            settings,
+            inputs: OscillatorInputs::new(),
+            output: MonoBuffer::new_silent(),
+            phase_accumulator: 0.0
+        }
+    }
+}
+
+oscillator_signal!(Square, |phase, increment| pulse(phase, increment, 0.5));
+
+#[no_mangle]
+pub extern "C" fn Square_new(settings: AudioSettings) -> Square {
+    Square::new(settings)
+}
+
+#[no_mangle]
+pub extern "C" fn Square_generate(square: &mut Square) {
+    square.generate()
+}
+
+// A band-limited triangle wave, which rises through zero
+// at the start of the cycle like a Sine.
+#[repr(C)]
+pub struct Triangle {
+    pub settings: AudioSettings,
+    pub inputs: OscillatorInputs,
+    pub output: MonoBuffer,
+    pub phase_accumulator: f32
+}
+
+impl Triangle {
+    pub fn new(settings: AudioSettings) -> Triangle {
+        Triangle {
+            settings,
+            inputs: OscillatorInputs::new(),
+            output: MonoBuffer::new_silent(),
+            phase_accumulator: 0.0
+        }
+    }
+}
+
+oscillator_signal!(Triangle, |phase: f32, increment: f32| {
+    let naive = if phase < 0.25 {
+        4.0 * phase
+    } else if phase < 0.75 {
+        2.0 - 4.0 * phase
+    } else {
+        4.0 * phase - 4.0
+    };
+
+    // The slope changes by 8 per cycle at each corner.
+    naive + 8.0 * increment * (poly_blamp(wrap(phase - 0.75), increment) -
+        poly_blamp(wrap(phase - 0.25), increment))
+});
+
+#[no_mangle]
+pub extern "C" fn Triangle_new(settings: AudioSettings) -> Triangle {
+    Triangle::new(settings)
+}
+
+#[no_mangle]
+pub extern "C" fn Triangle_generate(triangle: &mut Triangle) {
+    triangle.generate()
+}
+
+#[repr(C)]
+pub struct PulseInputs {
+    pub freq: Connection,
+    pub phase_offset: Connection,
+    pub mul: Connection,
+    pub add: Connection,
+    // The fraction of the cycle that the pulse is high for.
+    pub width: Connection
+}
+
+// A band-limited pulse wave with a variable width.
+#[repr(C)]
+pub struct Pulse {
+    pub settings: AudioSettings,
+    pub inputs: PulseInputs,
+    pub output: MonoBuffer,
+    pub phase_accumulator: f32
+}
+
+impl Pulse {
+    pub fn new(settings: AudioSettings) -> Pulse {
+        Pulse {
+            settings,
+            inputs: PulseInputs {
+                freq: Connection::new_constant(440.0),
+                phase_offset: Connection::new_constant(0.0),
+                mul: Connection::new_constant(1.0),
+                add: Connection::new_constant(0.0),
+                width: Connection::new_constant(0.5)
+            },
+            output: MonoBuffer::new_silent(),
+            phase_accumulator: 0.0
+        }
+    }
+}
+
+impl Signal for Pulse {
+    fn generate(&mut self) {
+        let width = &self.inputs.width;
+        oscillate(&self.settings, &self.inputs.freq,
+            &self.inputs.phase_offset, &self.inputs.mul, &self.inputs.add,
+            &mut self.phase_accumulator, &mut self.output,
+            |i, phase, increment| pulse(phase, increment, width.read(i)));
+    }
+
+    fn block_size(&self) -> usize {
+        self.settings.block_size
+    }
+
+    fn input(&self, index: usize) -> Option<&Connection> {
+        match index {
+            0 => Some(&self.inputs.freq),
+            1 => Some(&self.inputs.phase_offset),
+            2 => Some(&self.inputs.mul),
+            3 => Some(&self.inputs.add),
+            4 => Some(&self.inputs.width),
+            _ => None
+        }
+    }
+
+    fn input_mut(&mut self, index: usize) -> Option<&mut Connection> {
+        match index {
+            0 => Some(&mut self.inputs.freq),
+            1 => Some(&mut self.inputs.phase_offset),
+            2 => Some(&mut self.inputs.mul),
+            3 => Some(&mut self.inputs.add),
+            4 => Some(&mut self.inputs.width),
+            _ => None
+        }
+    }
+
+    fn output(&self, channel: usize) -> Option<&[f32; MAX_BLOCK_SIZE]> {
+        match channel {
+            0 => Some(&self.output.samples),
+            _ => None
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn Pulse_new(settings: AudioSettings) -> Pulse {
+    Pulse::new(settings)
+}
+
+#[no_mangle]
+pub extern "C" fn Pulse_generate(pulse: &mut Pulse) {
+    pulse.generate()
+}
+
+// How a Wavetable reads between the samples of its tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum Interpolation {
+    Truncate = 0,
+    Linear = 1,
+    Cubic = 2
+}
+
+impl Interpolation {
+    // Interpolation is set with a parameter, so it's rounded
+    // to the nearest mode.
+    pub fn from_parameter(value: f32) -> Interpolation {
+        match libm::roundf(value) as i32 {
+            i32::MIN..=0 => Interpolation::Truncate,
+            1 => Interpolation::Linear,
+            _ => Interpolation::Cubic
+        }
+    }
+
+    // Reads the table at a fractional position,
+    // wrapping around its end.
+    fn read(self, table: &[f32], position: f32) -> f32 {
+        let len = table.len();
+        let index = position as usize % len;
+        let fraction = position - libm::floorf(position);
+        let at = |offset: usize| table[(index + offset) % len];
+
+        match self {
+            Interpolation::Truncate => at(0),
+            Interpolation::Linear => at(0) + fraction * (at(1) - at(0)),
+            Interpolation::Cubic => {
+                // Catmull-Rom, through the two surrounding samples.
+                let (p0, p1, p2, p3) = (at(len - 1), at(0), at(1), at(2));
+                p1 + 0.5 * fraction * (p2 - p0 + fraction *
+                    (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3 + fraction *
+                        (3.0 * (p1 - p2) + p3 - p0)))
+            }
+        }
+    }
+}
+
+#[repr(C)]
+pub struct WavetableInputs {
+    pub freq: Connection,
+    pub phase_offset: Connection,
+    pub mul: Connection,
+    pub add: Connection,
+    // Crossfades from the first table (0) to the last (1).
+    pub morph: Connection
+}
+
+#[repr(C)]
+pub struct WavetableParameters {
+    // An Interpolation mode.
+    pub interpolation: f32
+}
+
+// Plays single-cycle tables that are owned by the caller,
+// stored one after another in a single array.
+// Like the other oscillators, its phase accumulator
+// is measured in cycles.
+#[repr(C)]
+pub struct Wavetable {
+    pub settings: AudioSettings,
+    pub inputs: WavetableInputs,
+    pub parameters: WavetableParameters,
+    // The tables, or null if there are none.
+    pub tables: *const f32,
+    pub table_length: usize,
+    pub num_tables: usize,
+    pub output: MonoBuffer,
+    pub phase_accumulator: f32
+}
+
+// Wavetables are moved to the audio thread,
+// and only ever read from their tables.
+unsafe impl Send for Wavetable {}
+
+impl Wavetable {
+    /// # Safety
+    /// The tables must contain num_tables * table_length samples,
+    /// and must not be moved, modified or dropped
+    /// while this Wavetable is still generating.
+    pub unsafe fn new(
+        settings: AudioSettings,
+        tables: *const f32,
+        table_length: usize,
+        num_tables: usize
+    ) -> Wavetable {
+        Wavetable {
+            settings,
+            inputs: WavetableInputs {
+                freq: Connection::new_constant(440.0),
+                phase_offset: Connection::new_constant(0.0),
+                mul: Connection::new_constant(1.0),
+                add: Connection::new_constant(0.0),
+                morph: Connection::new_constant(0.0)
+            },
+            parameters: WavetableParameters {
+                interpolation: Interpolation::Linear as i32 as f32
+            },
+            tables,
+            table_length,
+            num_tables,
+            output: MonoBuffer::new_silent(),
+            phase_accumulator: 0.0
+        }
+    }
+}
+
+impl Signal for Wavetable {
+    fn generate(&mut self) {
+        let tables = if self.tables.is_null() {
+            &[]
+        } else {
+            // The tables aren't part of the Wavetable, so they can be
+            // read while its phase and output are written.
+            unsafe {
+                core::slice::from_raw_parts(self.tables,
+                    self.table_length * self.num_tables)
+            }
+        };
+        let table_length = self.table_length;
+        let last_table = self.num_tables.saturating_sub(1);
+        let interpolation = Interpolation::from_parameter(
+            self.parameters.interpolation);
+        let morph = &self.inputs.morph;
+
+        oscillate(&self.settings, &self.inputs.freq,
+            &self.inputs.phase_offset, &self.inputs.mul, &self.inputs.add,
+            &mut self.phase_accumulator, &mut self.output,
+            |i, phase, _| {
+                if tables.is_empty() || table_length == 0 {
+                    return 0.0
+                }
+
+                let position = phase * table_length as f32;
+                let table = |n: usize| interpolation.read(
+                    &tables[n * table_length..(n + 1) * table_length],
+                    position);
+
+                let morph_position = morph.read(i).clamp(0.0, 1.0) *
+                    last_table as f32;
+                let first = (morph_position as usize).min(last_table);
+                let second = (first + 1).min(last_table);
+                let fraction = morph_position - first as f32;
+
+                let sample = table(first);
+                if fraction > 0.0 {
+                    sample + fraction * (table(second) - sample)
+                } else {
+                    sample
+                }
+            });
+    }
+
+    fn block_size(&self) -> usize {
+        self.settings.block_size
+    }
+
+    fn input(&self, index: usize) -> Option<&Connection> {
+        match index {
+            0 => Some(&self.inputs.freq),
+            1 => Some(&self.inputs.phase_offset),
+            2 => Some(&self.inputs.mul),
+            3 => Some(&self.inputs.add),
+            4 => Some(&self.inputs.morph),
+            _ => None
+        }
+    }
+
+    fn input_mut(&mut self, index: usize) -> Option<&mut Connection> {
+        match index {
+            0 => Some(&mut self.inputs.freq),
+            1 => Some(&mut self.inputs.phase_offset),
+            2 => Some(&mut self.inputs.mul),
+            3 => Some(&mut self.inputs.add),
+            4 => Some(&mut self.inputs.morph),
+            _ => None
+        }
+    }
+
+    fn parameter_mut(&mut self, index: usize) -> Option<&mut f32> {
+        match index {
+            0 => Some(&mut self.parameters.interpolation),
+            _ => None
+        }
+    }
+
+    fn output(&self, channel: usize) -> Option<&[f32; MAX_BLOCK_SIZE]> {
+        match channel {
+            0 => Some(&self.output.samples),
+            _ => None
+        }
+    }
+}
+
+/// # Safety
+/// See Wavetable::new().
+#[no_mangle]
+pub unsafe extern "C" fn Wavetable_new(
+    settings: AudioSettings,
+    tables: *const f32,
+    table_length: usize,
+    num_tables: usize
+) -> Wavetable {
+    Wavetable::new(settings, tables, table_length, num_tables)
+}
+
+#[no_mangle]
+pub extern "C" fn Wavetable_generate(wavetable: &mut Wavetable) {
+    wavetable.generate()
+}
+
+#[repr(C)]
+pub struct NoiseInputs {
+    pub mul: Connection,
+    pub add: Connection
+}
+
+impl NoiseInputs {
+    pub fn new() -> NoiseInputs {
+        NoiseInputs {
+            mul: Connection::new_constant(1.0),
+            add: Connection::new_constant(0.0)
+        }
+    }
+}
+
+impl Default for NoiseInputs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Advances a wyrand generator, whose whole state is its seed.
+fn wyrand(seed: &mut u64) -> u64 {
+    *seed = seed.wrapping_add(0xa076_1d64_78bd_642f);
+    let t = (*seed as u128)
+        .wrapping_mul((*seed ^ 0xe703_7ed1_a0b4_28db) as u128);
+    ((t >> 64) ^ t) as u64
+}
+
+// Generates a block of noise from white noise samples in [-1, 1),
+// which the filter shapes. The seed advances once per sample,
+// so a given seed always produces the same output, whatever the block size.
+fn generate_noise<F>(
+    settings: &AudioSettings,
+    inputs: &NoiseInputs,
+    seed: &mut u64,
+    output: &mut MonoBuffer,
+    mut filter: F
+) where F: FnMut(f32) -> f32 {
+    for i in 0..settings.block_size {
+        // The top 24 bits fill an f32's mantissa.
+        let white = (wyrand(seed) >> 40) as f32 / (1 << 24) as f32 * 2.0 - 1.0;
+        output.samples[i] = filter(white) * inputs.mul.read(i) +
+            inputs.add.read(i);
+    }
+}
+
+// Implements Signal for noise generators with NoiseInputs.
+macro_rules! noise_signal {
+    ($name:ident) => {
+        impl Signal for $name {
+            fn generate(&mut self) {
+                self.generate_block()
+            }
+
+            fn block_size(&self) -> usize {
+                self.settings.block_size
+            }
+
+            fn input(&self, index: usize) -> Option<&Connection> {
+                match index {
+                    0 => Some(&self.inputs.mul),
+                    1 => Some(&self.inputs.add),
+                    _ => None
+                }
+            }
+
+            fn input_mut(&mut self, index: usize) ->
+                Option<&mut Connection> {
+                match index {
+                    0 => Some(&mut self.inputs.mul),
+                    1 => Some(&mut self.inputs.add),
+                    _ => None
+                }
+            }
+
+            fn output(&self, channel: usize) ->
+                Option<&[f32; MAX_BLOCK_SIZE]> {
+                match channel {
+                    0 => Some(&self.output.samples),
+                    _ => None
+                }
+            }
+        }
+    }
+}
+
+// Uniformly distributed noise with a flat spectrum.
+#[repr(C)]
+pub struct WhiteNoise {
+    pub settings: AudioSettings,
+    pub inputs: NoiseInputs,
+    pub seed: u64,
+    pub output: MonoBuffer
+}
+
+impl WhiteNoise {
+    pub fn new(settings: AudioSettings, seed: u64) -> WhiteNoise {
+        WhiteNoise {
+            settings,
+            inputs: NoiseInputs::new(),
+            seed,
+            output: MonoBuffer::new_silent()
+        }
+    }
+
+    fn generate_block(&mut self) {
+        generate_noise(&self.settings, &self.inputs, &mut self.seed,
+            &mut self.output, |white| white);
+    }
+}
+
+noise_signal!(WhiteNoise);
+
+#[no_mangle]
+pub extern "C" fn WhiteNoise_new(settings: AudioSettings, seed: u64) ->
+    WhiteNoise {
+    WhiteNoise::new(settings, seed)
+}
+
+#[no_mangle]
+pub extern "C" fn WhiteNoise_generate(noise: &mut WhiteNoise) {
+    noise.generate()
+}
+
+// Noise whose power falls by 3 dB per octave, made by filtering
+// white noise with Paul Kellet's refined method.
+#[repr(C)]
+pub struct PinkNoise {
+    pub settings: AudioSettings,
+    pub inputs: NoiseInputs,
+    pub seed: u64,
+    pub filter: [f32; 7],
+    pub output: MonoBuffer
+}
+
+impl PinkNoise {
+    pub fn new(settings: AudioSettings, seed: u64) -> PinkNoise {
+        PinkNoise {
+            settings,
+            inputs: NoiseInputs::new(),
+            seed,
+            filter: [0.0; 7],
+            output: MonoBuffer::new_silent()
+        }
+    }
+
+    #[allow(clippy::excessive_precision)]
+    fn generate_block(&mut self) {
+        let b = &mut self.filter;
+        generate_noise(&self.settings, &self.inputs, &mut self.seed,
+            &mut self.output, |white| {
+                b[0] = 0.99886 * b[0] + white * 0.0555179;
+                b[1] = 0.99332 * b[1] + white * 0.0750759;
+                b[2] = 0.96900 * b[2] + white * 0.1538520;
+                b[3] = 0.86650 * b[3] + white * 0.3104856;
+                b[4] = 0.55000 * b[4] + white * 0.5329522;
+                b[5] = -0.7616 * b[5] - white * 0.0168980;
+                let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] +
+                    white * 0.5362;
+                b[6] = white * 0.115926;
+
+                // Roughly normalized to [-1, 1].
+                pink * 0.11
+            });
+    }
+}
+
+noise_signal!(PinkNoise);
+
+#[no_mangle]
+pub extern "C" fn PinkNoise_new(settings: AudioSettings, seed: u64) ->
+    PinkNoise {
+    PinkNoise::new(settings, seed)
+}
+
+#[no_mangle]
+pub extern "C" fn PinkNoise_generate(noise: &mut PinkNoise) {
+    noise.generate()
+}
+
+// Noise whose power falls by 6 dB per octave,
+// made by integrating white noise with a slight leak.
+#[repr(C)]
+pub struct BrownNoise {
+    pub settings: AudioSettings,
+    pub inputs: NoiseInputs,
+    pub seed: u64,
+    pub last_sample: f32,
+    pub output: MonoBuffer
+}
+
+impl BrownNoise {
+    pub fn new(settings: AudioSettings, seed: u64) -> BrownNoise {
+        BrownNoise {
+            settings,
+            inputs: NoiseInputs::new(),
+            seed,
+            last_sample: 0.0,
+            output: MonoBuffer::new_silent()
+        }
+    }
+
+    fn generate_block(&mut self) {
+        let last_sample = &mut self.last_sample;
+        generate_noise(&self.settings, &self.inputs, &mut self.seed,
+            &mut self.output, |white| {
+                *last_sample = (*last_sample + 0.02 * white) / 1.02;
+
+                // Roughly normalized to [-1, 1].
+                *last_sample * 3.5
+            });
+    }
+}
+
+noise_signal!(BrownNoise);
+
+#[no_mangle]
+pub extern "C" fn BrownNoise_new(settings: AudioSettings, seed: u64) ->
+    BrownNoise {
+    BrownNoise::new(settings, seed)
+}
+
+#[no_mangle]
+pub extern "C" fn BrownNoise_generate(noise: &mut BrownNoise) {
+    noise.generate()
+}
+
+pub const MAX_ENVELOPE_SEGMENTS: usize = 8;
+
+// The shape of an envelope segment on its way to its level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum Curve {
+    Linear = 0,
+    // Falls back to linear when either end is zero,
+    // or they have different signs.
+    Exponential = 1,
+    // Bends according to the segment's curvature:
+    // negative values rise quickly at first, positive ones slowly.
+    Custom = 2
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Segment {
+    // In seconds.
+    pub time: f32,
+    pub level: f32,
+    pub curve: Curve,
+    pub curvature: f32
+}
+
+impl Segment {
+    pub fn new(time: f32, level: f32, curve: Curve) -> Segment {
+        Segment {
+            time,
+            level,
+            curve,
+            curvature: 0.0
+        }
+    }
+
+    pub fn custom(time: f32, level: f32, curvature: f32) -> Segment {
+        Segment {
+            time,
+            level,
+            curve: Curve::Custom,
+            curvature
+        }
+    }
+
+    // The level at the specified fraction of the way through the segment.
+    fn interpolate(&self, start: f32, x: f32) -> f32 {
+        let linear = start + (self.level - start) * x;
+        match self.curve {
+            Curve::Exponential if start * self.level > 0.0 =>
+                start * libm::powf(self.level / start, x),
+            Curve::Custom if libm::fabsf(self.curvature) > 0.001 =>
+                start + (self.level - start) *
+                    (1.0 - libm::expf(self.curvature * x)) /
+                    (1.0 - libm::expf(self.curvature)),
+            _ => linear
+        }
+    }
+}
+
+#[repr(C)]
+pub struct EnvelopeInputs {
+    // The envelope starts when the gate rises above zero,
+    // and is released when it falls back.
+    pub gate: Connection,
+    pub mul: Connection,
+    pub add: Connection
+}
+
+// Moves from its start level through a series of segments each time
+// its gate opens. While the gate is open, the envelope holds at the end
+// of the sustain segment, or loops back to the loop segment from there;
+// when the gate closes, it continues from the segment after the sustain
+// segment. Segment indices are negative if there is no such segment.
+#[repr(C)]
+pub struct Envelope {
+    pub settings: AudioSettings,
+    pub inputs: EnvelopeInputs,
+    pub start_level: f32,
+    pub segments: [Segment; MAX_ENVELOPE_SEGMENTS],
+    pub num_segments: usize,
+    pub sustain_point: i32,
+    pub loop_point: i32,
+    // The current segment, which is past the last one
+    // before the gate opens and after the envelope ends.
+    pub segment: usize,
+    // The number of samples since the current segment started.
+    pub elapsed: f32,
+    pub segment_start: f32,
+    pub level: f32,
+    pub sustaining: bool,
+    pub last_gate: f32,
+    pub output: MonoBuffer
+}
+
+impl Envelope {
+    // Segments beyond MAX_ENVELOPE_SEGMENTS are ignored.
+    pub fn new(settings: AudioSettings, start_level: f32,
+        segments: &[Segment]) -> Envelope {
+        let num_segments = segments.len().min(MAX_ENVELOPE_SEGMENTS);
+        let mut envelope = Envelope {
+            settings,
+            inputs: EnvelopeInputs {
+                gate: Connection::new_constant(0.0),
+                mul: Connection::new_constant(1.0),
+                add: Connection::new_constant(0.0)
+            },
+            start_level,
+            segments: [Segment::new(0.0, 0.0, Curve::Linear);
+                MAX_ENVELOPE_SEGMENTS],
+            num_segments,
+            sustain_point: -1,
+            loop_point: -1,
+            segment: MAX_ENVELOPE_SEGMENTS,
+            elapsed: 0.0,
+            segment_start: start_level,
+            level: start_level,
+            sustaining: false,
+            last_gate: 0.0,
+            output: MonoBuffer::new_silent()
+        };
+        envelope.segments[..num_segments]
+            .copy_from_slice(&segments[..num_segments]);
+
+        envelope
+    }
+
+    // An envelope that rises to 1, decays to the sustain level
+    // and holds there until it's released back to 0.
+    pub fn adsr(settings: AudioSettings, attack: f32, decay: f32,
+        sustain: f32, release: f32) -> Envelope {
+        let mut envelope = Envelope::new(settings, 0.0, &[
+            Segment::custom(attack, 1.0, 0.0),
+            Segment::custom(decay, sustain, -4.0),
+            Segment::custom(release, 0.0, -4.0)
+        ]);
+        envelope.sustain_point = 1;
+
+        envelope
+    }
+
+    fn point(index: i32) -> Option<usize> {
+        usize::try_from(index).ok()
+    }
+
+    fn start_segment(&mut self, segment: usize, level: f32) {
+        self.segment = segment;
+        self.elapsed = 0.0;
+        self.segment_start = level;
+        self.level = level;
+        self.sustaining = false;
+    }
+
+    fn open(&mut self) {
+        self.start_segment(0, self.level);
+    }
+
+    fn release(&mut self) {
+        if let Some(sustain) = Envelope::point(self.sustain_point) {
+            if self.segment <= sustain {
+                self.start_segment(sustain + 1, self.level);
+            }
+        }
+    }
+
+    // Returns the level for the current sample,
+    // and advances to the next one.
+    fn advance(&mut self, gate_open: bool) -> f32 {
+        // Segments without a duration are skipped. Each one can only
+        // be skipped once per sample, so a loop of them sustains instead.
+        for _ in 0..self.num_segments {
+            if !self.at_empty_segment() {
+                break
+            }
+            self.complete(gate_open);
+        }
+
+        if self.at_empty_segment() {
+            self.level = self.segments[self.segment].level;
+            self.sustaining = true;
+        }
+
+        if self.segment >= self.num_segments || self.sustaining {
+            return self.level
+        }
+
+        let segment = self.segments[self.segment];
+        let duration = segment.time * self.settings.sample_rate;
+        let level = segment.interpolate(self.segment_start,
+            (self.elapsed / duration).min(1.0));
+        self.level = level;
+
+        self.elapsed += 1.0;
+        if self.elapsed >= duration {
+            let overshoot = self.elapsed - duration;
+            self.complete(gate_open);
+            self.elapsed = overshoot;
+        }
+
+        level
+    }
+
+    fn at_empty_segment(&self) -> bool {
+        self.segment < self.num_segments && !self.sustaining &&
+            self.segments[self.segment].time <= 0.0
+    }
+
+    fn complete(&mut self, gate_open: bool) {
+        let target = self.segments[self.segment].level;
+        let at_sustain = Envelope::point(self.sustain_point) ==
+            Some(self.segment);
+
+        if at_sustain && gate_open {
+            match Envelope::point(self.loop_point) {
+                Some(loop_point) if loop_point <= self.segment =>
+                    self.start_segment(loop_point, target),
+                _ => {
+                    self.level = target;
+                    self.sustaining = true;
+                }
+            }
+        } else {
+            let next = self.segment + 1;
+            self.start_segment(next, target);
+        }
+    }
+}
+
+impl Signal for Envelope {
+    fn generate(&mut self) {
+        for i in 0..self.settings.block_size {
+            let gate = self.inputs.gate.read(i);
+            if gate > 0.0 && self.last_gate <= 0.0 {
+                self.open();
+            } else if gate <= 0.0 && self.last_gate > 0.0 {
+                self.release();
+            }
+            self.last_gate = gate;
+
+            let level = self.advance(gate > 0.0);
+            self.output.samples[i] = level * self.inputs.mul.read(i) +
+                self.inputs.add.read(i);
+        }
+    }
+
+    fn block_size(&self) -> usize {
+        self.settings.block_size
+    }
+
+    fn input(&self, index: usize) -> Option<&Connection> {
+        match index {
+            0 => Some(&self.inputs.gate),
+            1 => Some(&self.inputs.mul),
+            2 => Some(&self.inputs.add),
+            _ => None
+        }
+    }
+
+    fn input_mut(&mut self, index: usize) -> Option<&mut Connection> {
+        match index {
+            0 => Some(&mut self.inputs.gate),
+            1 => Some(&mut self.inputs.mul),
+            2 => Some(&mut self.inputs.add),
+            _ => None
+        }
+    }
+
+    // Each segment has three parameters:
+    // its time, its level and its curvature.
+    fn parameter_mut(&mut self, index: usize) -> Option<&mut f32> {
+        let segment = self.segments[..self.num_segments]
+            .get_mut(index / 3)?;
+        match index % 3 {
+            0 => Some(&mut segment.time),
+            1 => Some(&mut segment.level),
+            _ => Some(&mut segment.curvature)
+        }
+    }
+
+    fn output(&self, channel: usize) -> Option<&[f32; MAX_BLOCK_SIZE]> {
+        match channel {
+            0 => Some(&self.output.samples),
+            _ => None
+        }
+    }
+}
+
+/// # Safety
+/// The segments must point to num_segments Segments.
+#[no_mangle]
+pub unsafe extern "C" fn Envelope_new(
+    settings: AudioSettings,
+    start_level: f32,
+    segments: *const Segment,
+    num_segments: usize
+) -> Envelope {
+    let segments = if segments.is_null() {
+        &[]
+    } else {
+        core::slice::from_raw_parts(segments, num_segments)
+    };
+
+    Envelope::new(settings, start_level, segments)
+}
+
+#[no_mangle]
+pub extern "C" fn Envelope_adsr(settings: AudioSettings, attack: f32,
+    decay: f32, sustain: f32, release: f32) -> Envelope {
+    Envelope::adsr(settings, attack, decay, sustain, release)
+}
+
+#[no_mangle]
+pub extern "C" fn Envelope_generate(envelope: &mut Envelope) {
+    envelope.generate()
+}
+
+// The shapes of the Biquad filter's frequency response,
+// which match those in the Audio EQ Cookbook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum FilterResponse {
+    LowPass = 0,
+    HighPass = 1,
+    // With a peak gain of 0 dB.
+    BandPass = 2,
+    Notch = 3,
+    Peaking = 4,
+    LowShelf = 5,
+    HighShelf = 6
+}
+
+// The coefficients of a trapezoidal state variable filter:
+// a1 to a3 set its cutoff and q, and m0 to m2 mix its
+// input, band pass and low pass into the response.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct BiquadCoefficients {
+    pub a1: f32,
+    pub a2: f32,
+    pub a3: f32,
+    pub m0: f32,
+    pub m1: f32,
+    pub m2: f32
+}
+
+impl BiquadCoefficients {
+    // The cutoff is clamped to just below the Nyquist frequency,
+    // and the q to a small positive value.
+    // Gain is in decibels, and only affects the peaking and shelf responses.
+    pub fn design(response: FilterResponse, sample_rate: f32, cutoff: f32,
+        q: f32, gain: f32) -> BiquadCoefficients {
+        let cutoff = cutoff.max(1.0).min(sample_rate * 0.49);
+        let q = q.max(0.01);
+        let a = libm::powf(10.0, gain / 40.0);
+        let mut g = libm::tanf(PI * cutoff / sample_rate);
+        let mut k = 1.0 / q;
+
+        let (m0, m1, m2) = match response {
+            FilterResponse::LowPass => (0.0, 0.0, 1.0),
+            FilterResponse::HighPass => (1.0, -k, -1.0),
+            FilterResponse::BandPass => (0.0, k, 0.0),
+            FilterResponse::Notch => (1.0, -k, 0.0),
+            FilterResponse::Peaking => {
+                k = 1.0 / (q * a);
+                (1.0, k * (a * a - 1.0), 0.0)
+            },
+            FilterResponse::LowShelf => {
+                g /= libm::sqrtf(a);
+                (1.0, k * (a - 1.0), a * a - 1.0)
+            },
+            FilterResponse::HighShelf => {
+                g *= libm::sqrtf(a);
+                (a * a, k * (1.0 - a) * a, 1.0 - a * a)
+            }
+        };
+
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        BiquadCoefficients {
+            a1,
+            a2: g * a1,
+            a3: g * g * a1,
+            m0,
+            m1,
+            m2
+        }
+    }
+}
+
+#[repr(C)]
+pub struct BiquadInputs {
+    pub source: Connection,
+    pub cutoff: Connection,
+    pub q: Connection,
+    // In decibels.
+    pub gain: Connection,
+    pub mul: Connection,
+    pub add: Connection
+}
+
+// A second-order filter whose cutoff, q and gain can be modulated
+// at audio rate. Its coefficients are only recalculated when one of them
+// changes. It's implemented as a trapezoidal state variable filter,
+// which has the same responses as a direct form biquad,
+// but stays stable however quickly its coefficients move.
+#[repr(C)]
+pub struct Biquad {
+    pub settings: AudioSettings,
+    pub inputs: BiquadInputs,
+    pub response: FilterResponse,
+    pub coefficients: BiquadCoefficients,
+    // The cutoff, q and gain the coefficients were designed for.
+    pub designed_for: [f32; 3],
+    pub state: [f32; 2],
+    pub output: MonoBuffer
+}
+
+impl Biquad {
+    pub fn new(settings: AudioSettings, response: FilterResponse) -> Biquad {
+        let designed_for = [1000.0, core::f32::consts::FRAC_1_SQRT_2, 0.0];
+        Biquad {
+            settings,
+            inputs: BiquadInputs {
+                source: Connection::new_constant(0.0),
+                cutoff: Connection::new_constant(designed_for[0]),
+                q: Connection::new_constant(designed_for[1]),
+                gain: Connection::new_constant(designed_for[2]),
+                mul: Connection::new_constant(1.0),
+                add: Connection::new_constant(0.0)
+            },
+            response,
+            coefficients: BiquadCoefficients::design(response,
+                settings.sample_rate, designed_for[0], designed_for[1],
+                designed_for[2]),
+            designed_for,
+            state: [0.0; 2],
+            output: MonoBuffer::new_silent()
+        }
+    }
+}
+
+impl Signal for Biquad {
+    fn generate(&mut self) {
+        for i in 0..self.settings.block_size {
+            let design = [self.inputs.cutoff.read(i), self.inputs.q.read(i),
+                self.inputs.gain.read(i)];
+            if design != self.designed_for {
+                let [cutoff, q, gain] = design;
+                self.designed_for = design;
+                self.coefficients = BiquadCoefficients::design(self.response,
+                    self.settings.sample_rate, cutoff, q, gain);
+            }
+
+            let BiquadCoefficients { a1, a2, a3, m0, m1, m2 } =
+                self.coefficients;
+            let [ic1, ic2] = self.state;
+            let v0 = self.inputs.source.read(i);
+            let v3 = v0 - ic2;
+            let v1 = a1 * ic1 + a2 * v3;
+            let v2 = ic2 + a2 * ic1 + a3 * v3;
+            let mut sample = m0 * v0 + m1 * v1 + m2 * v2;
+
+            // Recover from non-finite input or parameters
+            // rather than staying silent forever.
+            if sample.is_finite() {
+                self.state = [2.0 * v1 - ic1, 2.0 * v2 - ic2];
+            } else {
+                sample = 0.0;
+                self.state = [0.0; 2];
+            }
+
+            self.output.samples[i] = sample * self.inputs.mul.read(i) +
+                self.inputs.add.read(i);
+        }
+    }
+
+    fn block_size(&self) -> usize {
+        self.settings.block_size
+    }
+
+    fn input(&self, index: usize) -> Option<&Connection> {
+        match index {
+            0 => Some(&self.inputs.source),
+            1 => Some(&self.inputs.cutoff),
+            2 => Some(&self.inputs.q),
+            3 => Some(&self.inputs.gain),
+            4 => Some(&self.inputs.mul),
+            5 => Some(&self.inputs.add),
+            _ => None
+        }
+    }
+
+    fn input_mut(&mut self, index: usize) -> Option<&mut Connection> {
+        match index {
+            0 => Some(&mut self.inputs.source),
+            1 => Some(&mut self.inputs.cutoff),
+            2 => Some(&mut self.inputs.q),
+            3 => Some(&mut self.inputs.gain),
+            4 => Some(&mut self.inputs.mul),
+            5 => Some(&mut self.inputs.add),
+            _ => None
+        }
+    }
+
+    fn output(&self, channel: usize) -> Option<&[f32; MAX_BLOCK_SIZE]> {
+        match channel {
+            0 => Some(&self.output.samples),
+            _ => None
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn Biquad_new(settings: AudioSettings,
+    response: FilterResponse) -> Biquad {
+    Biquad::new(settings, response)
+}
+
+#[no_mangle]
+pub extern "C" fn Biquad_generate(biquad: &mut Biquad) {
+    biquad.generate()
+}
+
+
+#[repr(C)]
+pub struct FanInputs {
+    pub source: Connection
+}
+
+#[repr(C)]
+pub struct Fan {
+    pub settings: AudioSettings,
+    pub inputs: FanInputs,
+    pub output: MultichannelBuffer
+}
+
+impl Fan {
+    pub fn new(settings: AudioSettings) -> Fan {
+        Fan {
+            settings,
+            inputs: FanInputs {
+                source: Connection::new_constant(0.0)
+            },
+            output: MultichannelBuffer::new_silent()
+        }
+    }
+}
+
+impl Signal for Fan {
+    fn generate(&mut self) {
+        for i in 0..self.settings.block_size {
+            let sample = self.inputs.source.read(i);
+            for channel in self.output.channels.iter_mut()
+                .take(self.settings.num_channels) {
+                channel[i] = sample;
+            }
+        }
+    }
+
+    fn block_size(&self) -> usize {
+        self.settings.block_size
+    }
+
+    fn input(&self, index: usize) -> Option<&Connection> {
+        match index {
+            0 => Some(&self.inputs.source),
+            _ => None
+        }
+    }
+
+    fn input_mut(&mut self, index: usize) -> Option<&mut Connection> {
+        match index {
+            0 => Some(&mut self.inputs.source),
+            _ => None
+        }
+    }
+
+    fn output(&self, channel: usize) -> Option<&[f32; MAX_BLOCK_SIZE]> {
+        if channel < self.settings.num_channels {
+            Some(&self.output.channels[channel])
+        } else {
+            None
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn Fan_new(settings: AudioSettings) -> Fan {
+    Fan::new(settings)
+}
+
+#[no_mangle]
+pub extern "C" fn Fan_generate(fan: &mut Fan) {
+    fan.generate()
+}
+
+// The number of sources an AudioOut can mix.
+pub const MAX_OUTPUT_SOURCES: usize = 8;
+
+#[repr(C)]
+pub struct AudioOutInputs {
+    pub sources: [Connection; MAX_OUTPUT_SOURCES]
 }
 
-// TODO: Express these as Connections.
 #[repr(C)]
-pub struct SineInputs {
-    pub freq: MonoBuffer,
-    pub phase_offset: MonoBuffer,
-    pub mul: MonoBuffer,
-    pub add: MonoBuffer
+pub struct AudioOutParameters {
+    // The output channel that each source is mixed into.
+    // Sources with a negative channel are muted.
+    pub channels: [f32; MAX_OUTPUT_SOURCES]
 }
 
+// Mixes its sources into the environment's output channels.
+// By default, each source is mapped to the channel with
+// the same index; sources mapped to the same channel are summed.
 #[repr(C)]
-pub struct Sine {
+pub struct AudioOut {
     pub settings: AudioSettings,
-    pub inputs: SineInputs,
-    pub output: MonoBuffer,
-    pub phase_accumulator: f32
+    pub inputs: AudioOutInputs,
+    pub parameters: AudioOutParameters,
+    pub output: MultichannelBuffer
 }
 
-impl Sine {
-    pub fn new(settings: AudioSettings) -> Sine {
-        Sine {
-            settings,
+impl AudioOut {
+    pub fn new(settings: AudioSettings) -> AudioOut {
+        let mut channels = [0.0; MAX_OUTPUT_SOURCES];
+        for (i, channel) in channels.iter_mut().enumerate() {
+            *channel = i as f32;
+        }
 
-            // TODO: Remove hardcoding, introduce Connections,
-            // bind to Value signals, implement default merging.
-            inputs: SineInputs {
-                freq: MonoBuffer::new_with_value(440.0),
-                phase_offset: MonoBuffer::new_with_value(0.0),
-                mul: MonoBuffer::new_with_value(1.0),
-                add: MonoBuffer::new_with_value(0.0)
+        AudioOut {
+            settings,
+            inputs: AudioOutInputs {
+                sources: [Connection::new_constant(0.0); MAX_OUTPUT_SOURCES]
             },
-            output: MonoBuffer::new_silent(),
-            phase_accumulator: 0.0
+            parameters: AudioOutParameters {
+                channels
+            },
+            output: MultichannelBuffer::new_silent()
         }
     }
 }
 
-impl Signal for Sine {
+impl Signal for AudioOut {
     fn generate(&mut self) {
-        // TODO: Write a macro to handle to the core loop boilerplate
-        // and scaling/offset logic.
+        let block_size = self.settings.block_size;
+        let num_channels = self.settings.num_channels;
+        for channel in self.output.channels.iter_mut().take(num_channels) {
+            channel[..block_size].fill(0.0);
+        }
 
-        for i in 0..self.settings.block_size {
-            // TODO: Do negative values need to be handled?
-            let modulated_phase = (self.phase_accumulator +
-                self.inputs.phase_offset.samples[i]) % TWO_PI;
+        for (source, channel) in self.inputs.sources.iter()
+            .zip(self.parameters.channels.iter()) {
+            if *channel < 0.0 || *channel as usize >= num_channels {
+                continue;
+            }
 
-            self.output.samples[i] = libm::sinf(modulated_phase) *
-                self.inputs.mul.samples[i] +
-                self.inputs.add.samples[i];
+            let output = &mut self.output.channels[*channel as usize];
+            for (i, sample) in output[..block_size].iter_mut().enumerate() {
+                *sample += source.read(i);
+            }
+        }
+    }
 
-            let phase_step = self.inputs.freq.samples[i] /
-                self.settings.sample_rate * TWO_PI;
+    fn block_size(&self) -> usize {
+        self.settings.block_size
+    }
 
-            self.phase_accumulator += phase_step;
-            if self.phase_accumulator > TWO_PI {
-                self.phase_accumulator -= TWO_PI;
-            }
+    fn input(&self, index: usize) -> Option<&Connection> {
+        self.inputs.sources.get(index)
+    }
+
+    fn input_mut(&mut self, index: usize) -> Option<&mut Connection> {
+        self.inputs.sources.get_mut(index)
+    }
+
+    fn parameter_mut(&mut self, index: usize) -> Option<&mut f32> {
+        self.parameters.channels.get_mut(index)
+    }
+
+    fn output(&self, channel: usize) -> Option<&[f32; MAX_BLOCK_SIZE]> {
+        if channel < self.settings.num_channels {
+            Some(&self.output.channels[channel])
+        } else {
+            None
         }
     }
 }
 
 #[no_mangle]
-pub extern "C" fn Sine_new(settings: AudioSettings) -> Sine {
-    Sine::new(settings)
+pub extern "C" fn AudioOut_new(settings: AudioSettings) -> AudioOut {
+    AudioOut::new(settings)
 }
 
 #[no_mangle]
-pub extern "C" fn Sine_generate(sine: &mut Sine) {
-    sine.generate()
-}
-
-
-#[repr(C)]
-pub struct FanInputs {
-    pub source: MonoBuffer
+pub extern "C" fn AudioOut_generate(audio_out: &mut AudioOut) {
+    audio_out.generate()
 }
 
+// Outputs the frames read from an input device by an AudioInput,
+// one channel per device channel. Channels that the device
+// doesn't have are silent. Its block size has to match the AudioInput's,
+// since it always copies the start of the AudioInput's block.
 #[repr(C)]
-pub struct Fan {
+pub struct AudioIn {
     pub settings: AudioSettings,
-    pub inputs: FanInputs,
+    // The AudioInput's block, or null if there is no input.
+    pub source: *const MultichannelBuffer,
     pub output: MultichannelBuffer
 }
 
-impl Signal for Fan {
+// AudioIn signals are moved to the audio thread, which is also
+// the thread that reads the AudioInput's blocks.
+unsafe impl Send for AudioIn {}
+
+impl AudioIn {
+    /// # Safety
+    /// The source must not be moved or dropped
+    /// while this AudioIn is still generating.
+    pub unsafe fn new(settings: AudioSettings,
+        source: *const MultichannelBuffer) -> AudioIn {
+        AudioIn {
+            settings,
+            source,
+            output: MultichannelBuffer::new_silent()
+        }
+    }
+}
+
+impl Signal for AudioIn {
     fn generate(&mut self) {
-        for i in 0..self.settings.num_channels {
-            let mut channel = self.output.channels[i];
-            channel[0..self.settings.block_size].clone_from_slice(
-                &self.inputs.source.samples[0..self.settings.block_size]);
+        if self.source.is_null() {
+            return
+        }
+
+        let source = unsafe {
+            &*self.source
+        };
+        let block_size = self.settings.block_size;
+        for (channel, source_channel) in self.output.channels.iter_mut()
+            .zip(source.channels.iter()) {
+            channel[..block_size].copy_from_slice(
+                &source_channel[..block_size]);
         }
     }
+
+    fn block_size(&self) -> usize {
+        self.settings.block_size
+    }
+
+    fn output(&self, channel: usize) -> Option<&[f32; MAX_BLOCK_SIZE]> {
+        self.output.channels.get(channel)
+    }
 }
 
+/// # Safety
+/// See AudioIn::new().
 #[no_mangle]
-pub extern "C" fn Fan_generate(fan: &mut Fan) {
-    fan.generate()
+pub unsafe extern "C" fn AudioIn_new(settings: AudioSettings,
+    source: *const MultichannelBuffer) -> AudioIn {
+    AudioIn::new(settings, source)
+}
+
+#[no_mangle]
+pub extern "C" fn AudioIn_generate(audio_in: &mut AudioIn) {
+    audio_in.generate()
 }
 
 #[cfg(test)]
+#[allow(clippy::excessive_precision, clippy::bool_assert_comparison,
+    clippy::needless_range_loop)]
 mod tests {
     use super::*;
 
@@ -305,6 +1987,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn value_changes_are_output() {
+        let mut value_signal = Value::new(AudioSettings {
+            sample_rate: 44100.0,
+            block_size: 64,
+            num_channels: 1
+        });
+
+        *value_signal.parameter_mut(0).unwrap() = 0.5;
+        value_signal.generate();
+        assert_eq!(0.5, value_signal.output.samples[63]);
+
+        // Setting the value back to its initial value
+        // should overwrite the previous block.
+        *value_signal.parameter_mut(0).unwrap() = 0.0;
+        value_signal.generate();
+        assert_eq!(0.0, value_signal.output.samples[63]);
+        assert!(value_signal.parameter_mut(1).is_none());
+    }
+
     #[test]
     fn sin_is_output() {
         let expected: [f32;MAX_BLOCK_SIZE] = [
@@ -336,7 +2038,7 @@ mod tests {
             block_size: 64,
             num_channels: 1
         });
-        sine_signal.inputs.add = MonoBuffer::new_with_value(1.0);
+        sine_signal.inputs.add = Connection::new_constant(1.0);
 
         Sine_generate(&mut sine_signal);
 
@@ -347,6 +2049,54 @@ mod tests {
         );
     }
 
+    #[test]
+    fn connection_reads_at_step_size() {
+        let mut buffer = MonoBuffer::new_silent();
+        for i in 0..MAX_BLOCK_SIZE {
+            buffer.samples[i] = i as f32;
+        }
+
+        let (audio_rate, control_rate, strided) = unsafe {(
+            Connection_new(buffer.samples.as_ptr(), 1),
+            Connection_new(buffer.samples.as_ptr(), 0),
+            Connection_new(buffer.samples.as_ptr(), 2)
+        )};
+        let constant = Connection_new_constant(3.0);
+
+        assert_eq!(5.0, audio_rate.read(5));
+        assert_eq!(0.0, control_rate.read(5));
+        assert_eq!(10.0, strided.read(5));
+        assert_eq!((MAX_BLOCK_SIZE - 1) as f32,
+            strided.read(MAX_BLOCK_SIZE - 1),
+            "Reads past the end of the buffer are clamped");
+        assert_eq!(3.0, constant.read(5));
+        assert!(constant.is_constant());
+    }
+
+    #[test]
+    fn sin_reads_connected_freq() {
+        let mut freq = Value_new(AudioSettings {
+            sample_rate: 48000.0,
+            block_size: 48,
+            num_channels: 1
+        });
+        freq.parameters.value = 500.0;
+        freq.generate();
+
+        let mut sine_signal = Sine_new(AudioSettings {
+            sample_rate: 48000.0,
+            block_size: 48,
+            num_channels: 1
+        });
+        sine_signal.inputs.freq = unsafe {
+            Connection_new(freq.output.samples.as_ptr(), 1)
+        };
+        sine_signal.generate();
+
+        assert_f32_eq_with_error(500.0 / 48000.0 * TWO_PI * 48.0,
+            sine_signal.phase_accumulator, 0.0001);
+    }
+
     #[test]
     fn sin_accumulates_phase() {
         let mut sine_signal = Sine_new(AudioSettings {
@@ -370,6 +2120,73 @@ mod tests {
             0.000001);
     }
 
+    #[test]
+    fn fan_copies_source_to_each_channel() {
+        let mut fan = Fan_new(AudioSettings {
+            sample_rate: 44100.0,
+            block_size: 64,
+            num_channels: 2
+        });
+        fan.inputs.source = Connection::new_constant(0.5);
+
+        Fan_generate(&mut fan);
+
+        let mut expected = [0.0; MAX_BLOCK_SIZE];
+        for i in 0..64 {
+            expected[i] = 0.5;
+        }
+
+        assert_f32_buffer_eq(expected, fan.output.channels[0], 64);
+        assert_f32_buffer_eq(expected, fan.output.channels[1], 64);
+    }
+
+    #[test]
+    fn audio_out_mixes_sources_into_channels() {
+        let mut audio_out = AudioOut_new(AudioSettings {
+            sample_rate: 44100.0,
+            block_size: 64,
+            num_channels: 2
+        });
+        audio_out.inputs.sources[0] = Connection::new_constant(0.25);
+        audio_out.inputs.sources[1] = Connection::new_constant(0.5);
+        audio_out.inputs.sources[2] = Connection::new_constant(0.125);
+        *audio_out.parameter_mut(2).unwrap() = 1.0;
+        audio_out.inputs.sources[3] = Connection::new_constant(1.0);
+        *audio_out.parameter_mut(3).unwrap() = -1.0;
+
+        AudioOut_generate(&mut audio_out);
+        AudioOut_generate(&mut audio_out);
+
+        let mut left = [0.0; MAX_BLOCK_SIZE];
+        let mut right = [0.0; MAX_BLOCK_SIZE];
+        for i in 0..64 {
+            left[i] = 0.25;
+            right[i] = 0.625;
+        }
+
+        assert_f32_buffer_eq(left, *audio_out.output(0).unwrap(), 64);
+        assert_f32_buffer_eq(right, *audio_out.output(1).unwrap(), 64);
+        assert!(audio_out.output(2).is_none());
+    }
+
+    #[test]
+    fn audio_in_copies_its_source() {
+        let mut source = MultichannelBuffer::new_silent();
+        source.channels[1][3] = 0.25;
+
+        let mut audio_in = unsafe {
+            AudioIn_new(AudioSettings {
+                sample_rate: 44100.0,
+                block_size: 4,
+                num_channels: 2
+            }, &source)
+        };
+        AudioIn_generate(&mut audio_in);
+
+        assert_eq!(0.25, audio_in.output(1).unwrap()[3]);
+        assert!(audio_in.output(MAX_CHANNEL_COUNT).is_none());
+    }
+
     #[test]
     fn sin_limits_phase_to_twopi() {
         let mut sine_signal = Sine_new(AudioSettings {
@@ -385,4 +2202,433 @@ mod tests {
         assert!(sine_signal.phase_accumulator <= TWO_PI &&
             sine_signal.phase_accumulator >= 0.0);
     }
+
+    // One cycle per 64-sample block.
+    fn one_hertz() -> AudioSettings {
+        AudioSettings {
+            sample_rate: 64.0,
+            block_size: 64,
+            num_channels: 1
+        }
+    }
+
+    #[test]
+    fn oscillators_are_naive_away_from_their_edges() {
+        let mut saw = Saw_new(one_hertz());
+        saw.inputs.freq = Connection::new_constant(1.0);
+        Saw_generate(&mut saw);
+        assert_f32_eq_with_error(0.0, saw.output.samples[0], 0.00001);
+        assert_f32_eq_with_error(0.5, saw.output.samples[16], 0.00001);
+        assert_f32_eq_with_error(-0.5, saw.output.samples[48], 0.00001);
+
+        let mut square = Square_new(one_hertz());
+        square.inputs.freq = Connection::new_constant(1.0);
+        Square_generate(&mut square);
+        assert_eq!(1.0, square.output.samples[8]);
+        assert_eq!(-1.0, square.output.samples[40]);
+
+        let mut triangle = Triangle_new(one_hertz());
+        triangle.inputs.freq = Connection::new_constant(1.0);
+        Triangle_generate(&mut triangle);
+        assert_f32_eq_with_error(0.5, triangle.output.samples[8], 0.00001);
+        assert_f32_eq_with_error(-0.5, triangle.output.samples[40], 0.00001);
+
+        assert!(saw.phase_accumulator >= 0.0 && saw.phase_accumulator < 1.0);
+    }
+
+    #[test]
+    fn oscillator_edges_are_band_limited() {
+        let mut saw = Saw_new(one_hertz());
+        saw.inputs.freq = Connection::new_constant(1.0);
+        Saw_generate(&mut saw);
+        assert_f32_eq_with_error(0.0, saw.output.samples[32], 0.00001);
+        assert!(saw.output.samples[31] < 1.0 && saw.output.samples[33] > -1.0,
+            "The samples around the step are smoothed");
+
+        let mut square = Square_new(one_hertz());
+        square.inputs.freq = Connection::new_constant(1.0);
+        Square_generate(&mut square);
+        assert_f32_eq_with_error(0.0, square.output.samples[0], 0.00001);
+        assert_f32_eq_with_error(0.0, square.output.samples[32], 0.00001);
+
+        let mut triangle = Triangle_new(one_hertz());
+        triangle.inputs.freq = Connection::new_constant(1.0);
+        Triangle_generate(&mut triangle);
+        let peak = triangle.output.samples[16];
+        assert!(peak < 1.0 && peak > 0.95, "The corner is rounded: {}", peak);
+    }
+
+    #[test]
+    fn pulse_width_sets_the_duty_cycle() {
+        let mut pulse = Pulse_new(one_hertz());
+        pulse.inputs.freq = Connection::new_constant(1.0);
+        *pulse.input_mut(4).unwrap() = Connection::new_constant(0.25);
+        pulse.inputs.mul = Connection::new_constant(0.5);
+        Pulse_generate(&mut pulse);
+
+        // The samples on each edge are halfway through the step.
+        assert!(pulse.output.samples[1..16].iter().all(|s| *s > 0.0));
+        assert!(pulse.output.samples[17..64].iter().all(|s| *s < 0.0));
+        assert_eq!(-0.5, pulse.output.samples[40]);
+        assert!(pulse.input(5).is_none());
+    }
+
+    // Eight samples per cycle of a four-sample table.
+    fn wavetable(tables: &[f32], num_tables: usize, interpolation: Interpolation)
+        -> Wavetable {
+        let mut wavetable = unsafe {
+            Wavetable_new(AudioSettings {
+                sample_rate: 64.0,
+                block_size: 8,
+                num_channels: 1
+            }, tables.as_ptr(), tables.len() / num_tables, num_tables)
+        };
+        wavetable.inputs.freq = Connection::new_constant(8.0);
+        *wavetable.parameter_mut(0).unwrap() = interpolation as i32 as f32;
+
+        wavetable
+    }
+
+    #[test]
+    fn wavetables_are_interpolated() {
+        let table = [0.0, 1.0, 0.0, -1.0];
+
+        let mut truncated = wavetable(&table, 1, Interpolation::Truncate);
+        Wavetable_generate(&mut truncated);
+        assert_eq!([0.0, 0.0, 1.0, 1.0, 0.0, 0.0, -1.0, -1.0],
+            truncated.output.samples[0..8]);
+
+        let mut linear = wavetable(&table, 1, Interpolation::Linear);
+        Wavetable_generate(&mut linear);
+        assert_eq!([0.0, 0.5, 1.0, 0.5, 0.0, -0.5, -1.0, -0.5],
+            linear.output.samples[0..8]);
+
+        let mut cubic = wavetable(&table, 1, Interpolation::Cubic);
+        Wavetable_generate(&mut cubic);
+        assert_f32_eq_with_error(1.0, cubic.output.samples[2], 0.00001);
+        assert_f32_eq_with_error(0.625, cubic.output.samples[1], 0.00001);
+        assert_f32_eq_with_error(-0.625, cubic.output.samples[5], 0.00001);
+
+        assert_eq!(Interpolation::Cubic, Interpolation::from_parameter(7.0));
+        assert_eq!(Interpolation::Truncate,
+            Interpolation::from_parameter(-1.0));
+    }
+
+    #[test]
+    fn wavetables_morph_between_tables() {
+        let tables = [1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0];
+        let mut morphing = wavetable(&tables, 2, Interpolation::Linear);
+        morphing.inputs.morph = Connection::new_constant(0.25);
+        Wavetable_generate(&mut morphing);
+        assert_eq!([0.5; 8], morphing.output.samples[0..8]);
+
+        *morphing.input_mut(4).unwrap() = Connection::new_constant(2.0);
+        Wavetable_generate(&mut morphing);
+        assert_eq!([-1.0; 8], morphing.output.samples[0..8],
+            "The morph is clamped to the last table");
+    }
+
+    #[test]
+    fn wavetables_without_tables_are_silent() {
+        let mut empty = unsafe {
+            Wavetable::new(one_hertz(), core::ptr::null(), 0, 0)
+        };
+        empty.inputs.add = Connection::new_constant(0.25);
+        Wavetable_generate(&mut empty);
+        assert_eq!([0.25; 64], empty.output.samples[0..64]);
+    }
+
+    // The mean absolute difference between consecutive samples.
+    fn roughness(samples: &[f32]) -> f32 {
+        samples.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum::<f32>() /
+            (samples.len() - 1) as f32
+    }
+
+    #[test]
+    fn noise_is_reproducible_from_its_seed() {
+        let settings = AudioSettings {
+            sample_rate: 44100.0,
+            block_size: 64,
+            num_channels: 1
+        };
+        let mut first = WhiteNoise_new(settings, 42);
+        let mut second = WhiteNoise_new(settings, 42);
+        let mut other = WhiteNoise_new(settings, 43);
+
+        for _ in 0..3 {
+            WhiteNoise_generate(&mut first);
+            WhiteNoise_generate(&mut second);
+            WhiteNoise_generate(&mut other);
+            assert_eq!(first.output.samples, second.output.samples);
+            assert_ne!(first.output.samples, other.output.samples);
+        }
+
+        assert!(first.output.samples.iter()
+            .all(|sample| (-1.0..1.0).contains(sample)));
+    }
+
+    #[test]
+    fn noise_is_independent_of_block_size() {
+        let settings = |block_size| AudioSettings {
+            sample_rate: 44100.0,
+            block_size,
+            num_channels: 1
+        };
+        let mut whole = PinkNoise_new(settings(128), 42);
+        let mut halves = PinkNoise_new(settings(64), 42);
+        PinkNoise_generate(&mut whole);
+        PinkNoise_generate(&mut halves);
+        assert_eq!(whole.output.samples[0..64], halves.output.samples[0..64]);
+        PinkNoise_generate(&mut halves);
+        assert_eq!(whole.output.samples[64..128],
+            halves.output.samples[0..64]);
+    }
+
+    #[test]
+    fn colored_noise_is_smoother_than_white_noise() {
+        let settings = AudioSettings {
+            sample_rate: 44100.0,
+            block_size: 128,
+            num_channels: 1
+        };
+        let mut white = WhiteNoise_new(settings, 7);
+        let mut pink = PinkNoise_new(settings, 7);
+        let mut brown = BrownNoise_new(settings, 7);
+        for _ in 0..8 {
+            WhiteNoise_generate(&mut white);
+            PinkNoise_generate(&mut pink);
+            BrownNoise_generate(&mut brown);
+        }
+
+        let white = roughness(&white.output.samples);
+        let pink = roughness(&pink.output.samples);
+        let brown = roughness(&brown.output.samples);
+        assert!(white > pink && pink > brown,
+            "White: {}, pink: {}, brown: {}", white, pink, brown);
+        assert!(brown.is_finite() && pink.is_finite());
+    }
+
+    fn ten_hertz() -> AudioSettings {
+        AudioSettings {
+            sample_rate: 10.0,
+            block_size: 32,
+            num_channels: 1
+        }
+    }
+
+    #[test]
+    fn envelope_follows_its_gate_sample_accurately() {
+        let mut gate = MonoBuffer::new_silent();
+        for sample in gate.samples[2..20].iter_mut() {
+            *sample = 1.0;
+        }
+
+        let mut envelope = Envelope::new(ten_hertz(), 0.0, &[
+            Segment::new(0.4, 1.0, Curve::Linear),
+            Segment::new(0.4, 0.5, Curve::Linear),
+            Segment::new(0.4, 0.0, Curve::Linear)
+        ]);
+        envelope.sustain_point = 1;
+        envelope.inputs.gate = unsafe {
+            Connection::new(&gate.samples, 1)
+        };
+        Envelope_generate(&mut envelope);
+
+        let mut expected = [0.0; MAX_BLOCK_SIZE];
+        expected[2..10].copy_from_slice(
+            &[0.0, 0.25, 0.5, 0.75, 1.0, 0.875, 0.75, 0.625]);
+        for sample in expected[10..21].iter_mut() {
+            *sample = 0.5;
+        }
+        expected[21..24].copy_from_slice(&[0.375, 0.25, 0.125]);
+        assert_f32_buffer_eq(expected, envelope.output.samples, 32);
+    }
+
+    #[test]
+    fn envelope_loops_while_its_gate_is_open() {
+        let mut envelope = Envelope::new(ten_hertz(), 0.0, &[
+            Segment::new(0.2, 1.0, Curve::Linear),
+            Segment::new(0.2, 0.0, Curve::Linear)
+        ]);
+        envelope.sustain_point = 1;
+        envelope.loop_point = 0;
+        envelope.inputs.gate = Connection::new_constant(1.0);
+        Envelope_generate(&mut envelope);
+
+        for (i, sample) in envelope.output.samples[0..32].iter().enumerate() {
+            assert_f32_eq_with_error([0.0, 0.5, 1.0, 0.5][i % 4], *sample,
+                0.0001);
+        }
+    }
+
+    #[test]
+    fn envelope_sustains_through_empty_loops() {
+        let mut envelope = Envelope::new(ten_hertz(), 0.0, &[
+            Segment::new(0.0, 1.0, Curve::Linear),
+            Segment::new(0.0, 0.5, Curve::Linear),
+            Segment::new(0.2, 0.0, Curve::Linear)
+        ]);
+        envelope.sustain_point = 1;
+        envelope.loop_point = 0;
+        envelope.inputs.gate = Connection::new_constant(1.0);
+        Envelope_generate(&mut envelope);
+        assert_eq!([0.5; 32], envelope.output.samples[0..32]);
+
+        envelope.inputs.gate = Connection::new_constant(0.0);
+        Envelope_generate(&mut envelope);
+        assert_eq!([0.5, 0.25, 0.0], envelope.output.samples[0..3]);
+    }
+
+    #[test]
+    fn envelope_segments_follow_their_curves() {
+        let exponential = Segment::new(1.0, 0.25, Curve::Exponential);
+        assert_f32_eq_with_error(0.5, exponential.interpolate(1.0, 0.5),
+            0.0001);
+        assert_f32_eq_with_error(0.125, exponential.interpolate(0.0, 0.5),
+            0.0001);
+
+        let custom = Segment::custom(1.0, 1.0, -4.0);
+        assert_f32_eq_with_error(0.8808, custom.interpolate(0.0, 0.5),
+            0.0001);
+        assert_f32_eq_with_error(1.0, custom.interpolate(0.0, 1.0), 0.0001);
+    }
+
+    #[test]
+    fn adsr_holds_its_sustain_level_until_released() {
+        let mut adsr = Envelope_adsr(ten_hertz(), 0.2, 0.2, 0.6, 0.2);
+        assert_eq!(Some(&mut 0.6), adsr.parameter_mut(4));
+        assert_eq!(None, adsr.parameter_mut(9));
+
+        adsr.inputs.gate = Connection::new_constant(1.0);
+        Envelope_generate(&mut adsr);
+        assert_f32_eq_with_error(1.0, adsr.output.samples[2], 0.0001);
+        assert_f32_eq_with_error(0.6, adsr.output.samples[31], 0.0001);
+
+        adsr.inputs.gate = Connection::new_constant(0.0);
+        Envelope_generate(&mut adsr);
+        assert_f32_eq_with_error(0.6, adsr.output.samples[0], 0.0001);
+        assert!(adsr.output.samples[1] < 0.6);
+        assert_eq!([0.0; 28], adsr.output.samples[4..32]);
+    }
+
+    // The gain of a filter at the specified frequency,
+    // measured by correlating its output with a cosine at its input
+    // once it has settled.
+    fn filter_gain(response: FilterResponse, q: f32, gain: f32, freq: f32,
+        sample_rate: f32) -> f32 {
+        let settings = AudioSettings {
+            sample_rate,
+            block_size: 128,
+            num_channels: 1
+        };
+        let mut source = MonoBuffer::new_silent();
+        let mut filter = Biquad_new(settings, response);
+        filter.inputs.source = unsafe {
+            Connection::new(&source.samples, 1)
+        };
+        filter.inputs.q = Connection::new_constant(q);
+        filter.inputs.gain = Connection::new_constant(gain);
+
+        let (mut in_phase, mut quadrature, mut power) = (0.0, 0.0, 0.0);
+        for block in 0..200 {
+            for i in 0..128 {
+                let phase = TWO_PI * freq * (block * 128 + i) as f32 /
+                    sample_rate;
+                source.samples[i] = libm::cosf(phase);
+            }
+            Biquad_generate(&mut filter);
+
+            if block >= 50 {
+                for i in 0..128 {
+                    let phase = TWO_PI * freq * (block * 128 + i) as f32 /
+                        sample_rate;
+                    let sample = filter.output.samples[i];
+                    in_phase += sample * libm::cosf(phase);
+                    quadrature += sample * libm::sinf(phase);
+                    power += libm::cosf(phase) * libm::cosf(phase);
+                }
+            }
+        }
+
+        libm::hypotf(in_phase, quadrature) / power
+    }
+
+    #[test]
+    fn biquad_responses_match_their_known_gains() {
+        let q = core::f32::consts::FRAC_1_SQRT_2;
+        let six_db = libm::powf(10.0, 6.0 / 20.0);
+
+        for sample_rate in [44100.0, 48000.0, 96000.0] {
+            let nyquist = sample_rate / 2.0;
+            let at = |response, q, gain, freq| filter_gain(response, q, gain,
+                freq, sample_rate);
+
+            assert_f32_eq_with_error(1.0,
+                at(FilterResponse::LowPass, q, 0.0, 0.0), 0.01);
+            assert_f32_eq_with_error(q,
+                at(FilterResponse::LowPass, q, 0.0, 1000.0), 0.01);
+            assert!(at(FilterResponse::LowPass, q, 0.0, 10000.0) < 0.011);
+
+            assert_f32_eq_with_error(q,
+                at(FilterResponse::HighPass, q, 0.0, 1000.0), 0.01);
+            assert_f32_eq_with_error(1.0,
+                at(FilterResponse::HighPass, q, 0.0, nyquist), 0.01);
+            assert!(at(FilterResponse::HighPass, q, 0.0, 100.0) < 0.011);
+
+            assert_f32_eq_with_error(1.0,
+                at(FilterResponse::BandPass, 2.0, 0.0, 1000.0), 0.01);
+            assert!(at(FilterResponse::BandPass, 2.0, 0.0, 100.0) < 0.06);
+
+            assert_f32_eq_with_error(0.0,
+                at(FilterResponse::Notch, 2.0, 0.0, 1000.0), 0.01);
+            assert_f32_eq_with_error(1.0,
+                at(FilterResponse::Notch, 2.0, 0.0, 0.0), 0.01);
+
+            assert_f32_eq_with_error(six_db,
+                at(FilterResponse::Peaking, 1.0, 6.0, 1000.0), 0.01);
+            assert_f32_eq_with_error(1.0,
+                at(FilterResponse::Peaking, 1.0, 6.0, 0.0), 0.01);
+
+            assert_f32_eq_with_error(six_db,
+                at(FilterResponse::LowShelf, q, 6.0, 0.0), 0.01);
+            assert_f32_eq_with_error(1.0,
+                at(FilterResponse::LowShelf, q, 6.0, nyquist), 0.01);
+
+            assert_f32_eq_with_error(1.0 / six_db,
+                at(FilterResponse::HighShelf, q, -6.0, nyquist), 0.01);
+            assert_f32_eq_with_error(1.0,
+                at(FilterResponse::HighShelf, q, -6.0, 0.0), 0.01);
+        }
+    }
+
+    #[test]
+    fn biquad_stays_stable_under_fast_modulation() {
+        let settings = AudioSettings {
+            sample_rate: 44100.0,
+            block_size: 64,
+            num_channels: 1
+        };
+        let mut cutoff = MonoBuffer::new_silent();
+        for (i, sample) in cutoff.samples.iter_mut().enumerate() {
+            *sample = if i % 2 == 0 { 20.0 } else { 30000.0 };
+        }
+
+        for response in [FilterResponse::LowPass, FilterResponse::Peaking,
+            FilterResponse::HighShelf] {
+            let mut filter = Biquad_new(settings, response);
+            filter.inputs.source = Connection::new_constant(1.0);
+            filter.inputs.cutoff = unsafe {
+                Connection::new(&cutoff.samples, 1)
+            };
+            filter.inputs.q = Connection::new_constant(20.0);
+            filter.inputs.gain = Connection::new_constant(12.0);
+
+            for _ in 0..1000 {
+                Biquad_generate(&mut filter);
+                assert!(filter.output.samples[0..64].iter()
+                    .all(|sample| sample.abs() < 1000.0),
+                    "{:?} became unstable", response);
+            }
+        }
+    }
 }